@@ -0,0 +1,104 @@
+//! How long one tick of the expensive systems takes as the population grows. Every iteration runs the system once
+//! on a freshly populated world, the same one every time, so the throughput criterion reports is in ticks per second
+//! and the samples are comparable. Building the world isn't measured.
+//! Avoidance is off, as in the default config, so `steer_species` skips its all-pairs avoid loop. `fight_species`
+//! still compares every pair of species and stops at 10k.
+//! `cargo bench -- fight_species/1000` runs a single case, the 50k ones take a while
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use bevy::prelude::*;
+use bevy::ecs::event::event_update_system;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, SamplingMode, Throughput};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::behavior::{behaviors, steer_species};
+use evolution_sim_bevy::config::SimConfig;
+use evolution_sim_bevy::fight::fight_species;
+use evolution_sim_bevy::food_source::*;
+use evolution_sim_bevy::reproduce::{update_reproduction, Reproduce};
+use evolution_sim_bevy::water_source::WaterSource;
+
+const AGENT_COUNTS: [usize; 4] = [100, 1_000, 10_000, 50_000];
+const ALL_PAIRS_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+const AGENTS_PER_FOOD: usize = 10;
+const WATER_SOURCES: usize = 4;
+
+
+/// Two races spread evenly over the map, with a food location for every `AGENTS_PER_FOOD` agents
+fn populated_app(name: &str, n: usize) -> App {
+    let mut app = test_app(test_config(&format!("bench_{}_{}", name, n)), (BehaviorPlugin, ReproductionPlugin));
+    let config = app.world.resource::<SimConfig>().clone();
+    let mut rng = ChaCha8Rng::seed_from_u64(n as u64);
+    let random_position = |rng: &mut ChaCha8Rng| Vec3::new(
+        rng.gen_range(-0.5..0.5) * config.world.map_width,
+        rng.gen_range(-0.5..0.5) * config.world.map_height,
+        0.,
+    );
+
+    for i in 0..n {
+        let race = if i % 2 == 0 { RED } else { BLUE };
+        let position = random_position(&mut rng);
+        spawn_species(&mut app, race, position);
+    }
+
+    let mut food_locations = Vec::new();
+    for _ in 0..(n / AGENTS_PER_FOOD).max(1) {
+        let position = random_position(&mut rng);
+        food_locations.push(position.truncate());
+        app.world.spawn((Transform::from_translation(position), FoodSource::new(position, &config.food, &mut rng)));
+    }
+    app.world.resource_mut::<FoodLocations>().position = food_locations;
+
+    for _ in 0..WATER_SOURCES {
+        let position = random_position(&mut rng);
+        app.world.spawn((Transform::from_translation(position), WaterSource::new(position, config.water.capacity)));
+    }
+    app
+}
+
+
+fn bench_system(c: &mut Criterion, name: &str, counts: &[usize], add_systems: impl Fn(&mut Schedule)) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    // the setup takes much longer than a tick, keep the number of iterations down
+    group.sampling_mode(SamplingMode::Flat);
+    group.throughput(Throughput::Elements(1));
+    for &n in counts {
+        let setup = || {
+            let mut app = populated_app(name, n);
+            let mut schedule = Schedule::default();
+            add_systems(&mut schedule);
+            schedule.initialize(&mut app.world).expect("the benchmarked systems can't be scheduled");
+            (app, schedule)
+        };
+        // the world is returned so dropping it isn't measured either
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| b.iter_batched(
+            setup,
+            |(mut app, mut schedule)| {
+                schedule.run(&mut app.world);
+                (app, schedule)
+            },
+            BatchSize::PerIteration,
+        ));
+    }
+    group.finish();
+}
+
+
+fn systems(c: &mut Criterion) {
+    bench_system(c, "behaviors", &AGENT_COUNTS, |schedule| { schedule.add_systems((steer_species, behaviors).chain()); });
+    bench_system(c, "fight_species", &ALL_PAIRS_COUNTS, |schedule| { schedule.add_systems(fight_species); });
+    // the events are dropped at the end of the tick, like the app does
+    bench_system(c, "update_reproduction", &AGENT_COUNTS, |schedule| {
+        schedule.add_systems((update_reproduction, event_update_system::<Reproduce>).chain());
+    });
+    bench_system(c, "spawn_food_replenish", &AGENT_COUNTS, |schedule| { schedule.add_systems(spawn_food_replenish); });
+}
+
+
+criterion_group!(benches, systems);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use bevy::time::Virtual;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::clock::SimClock;
+use crate::config::WorldConfig;
+use crate::replay::*;
+use crate::snapshot::*;
+
+const AUTOSAVE_INTERVAL_MIN: f32 = 5.;
+const CHECKPOINTS_KEPT: usize = 5;
+const CHECKPOINT_DIR: &str = "checkpoints";
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutosaveConfig {
+    pub enabled: bool,
+    pub interval_min: f32, // simulated minutes between checkpoints
+    pub keep: usize,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_min: AUTOSAVE_INTERVAL_MIN,
+            keep: CHECKPOINTS_KEPT,
+        }
+    }
+}
+
+
+#[derive(Resource, Default)]
+pub struct Autosave {
+    pub last_elapsed: f64,
+}
+
+
+/// Asks at startup whether to continue from the newest checkpoint. The simulation is paused until it is answered
+#[derive(Resource, Default)]
+pub struct ResumePrompt {
+    pub checkpoint: Option<PathBuf>,
+}
+
+
+pub fn checkpoint_dir(world: &WorldConfig) -> PathBuf {
+    PathBuf::from(&world.output_dir).join(CHECKPOINT_DIR)
+}
+
+
+/// Every checkpoint in the directory, oldest first. Sorted by when they were written rather than by name,
+/// a new run starts its clock at 0 again
+pub fn list_checkpoints(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new(); };
+    let mut checkpoints: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+        .filter_map(|path| Some((fs::metadata(&path).and_then(|m| m.modified()).ok()?, path)))
+        .collect();
+    checkpoints.sort();
+    checkpoints.into_iter().map(|(_, path)| path).collect()
+}
+
+
+
+/// Delete the oldest checkpoints so only the newest `keep` are left
+pub fn prune_checkpoints(dir: &Path, keep: usize) {
+    let checkpoints = list_checkpoints(dir);
+    let n_remove = checkpoints.len().saturating_sub(keep.max(1));
+    for old in checkpoints.iter().take(n_remove) {
+        if let Err(e) = fs::remove_file(old) {
+            warn!("Could not remove the old checkpoint {:?}: {}", old, e);
+        }
+    }
+}
+
+
+
+/// Write a checkpoint every `interval_min` simulated minutes. The oldest ones are deleted once it is written, see
+/// `save_snapshot`. The replay recording is saved along with it, when there is one
+pub fn autosave(
+    mut autosave: ResMut<Autosave>,
+    clock: Res<SimClock>,
+    config: Res<AutosaveConfig>,
+    world: Res<WorldConfig>,
+    mut save: EventWriter<SaveSnapshot>,
+    mut save_replay: EventWriter<SaveReplay>,
+    replay: Res<ReplayConfig>,
+) {
+    let previous = std::mem::replace(&mut autosave.last_elapsed, clock.elapsed);
+    if !config.enabled { return; }
+
+    let interval = config.interval_min as f64 * 60.;
+    // loading a snapshot moves the clock, only count time that actually passed
+    if clock.elapsed < previous || clock.elapsed - previous > interval { return; }
+    if (clock.elapsed / interval).floor() <= (previous / interval).floor() { return; }
+
+    let dir = checkpoint_dir(&world);
+    save.send(SaveSnapshot(dir.join(format!("checkpoint_{}.ron", clock.elapsed as u64))));
+    if replay.enabled {
+        save_replay.send(SaveReplay(default_replay_path(&world)));
+    }
+}
+
+
+
+pub fn offer_resume(
+    mut prompt: ResMut<ResumePrompt>,
+    mut time: ResMut<Time<Virtual>>,
+    world: Res<WorldConfig>,
+    replay: Res<Replay>,
+) {
+    // a replay plays back its own run
+    if replay.mode == ReplayMode::Playback { return; }
+    prompt.checkpoint = list_checkpoints(&checkpoint_dir(&world)).pop();
+    if prompt.checkpoint.is_some() {
+        time.pause();
+    }
+}
+
+
+
+pub fn resume_prompt_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut prompt: ResMut<ResumePrompt>,
+    mut time: ResMut<Time<Virtual>>,
+    mut load: EventWriter<LoadSnapshot>,
+) {
+    let Some(checkpoint) = prompt.checkpoint.clone() else { return; };
+    let name = checkpoint.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+    let mut answered = false;
+    egui::Window::new("Resume?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("Found the checkpoint {}", name));
+            ui.horizontal(|ui| {
+                if ui.button("Resume").clicked() {
+                    commands.insert_resource(ResumeFrom(checkpoint.clone()));
+                    load.send(LoadSnapshot(checkpoint.clone()));
+                    answered = true;
+                }
+                if ui.button("Start new").clicked() {
+                    answered = true;
+                }
+            });
+        });
+
+    if answered {
+        prompt.checkpoint = None;
+        time.unpause();
+    }
+}
@@ -0,0 +1,265 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::config::*;
+use crate::termination::*;
+
+const SUMMARY_FILE: &str = "summary.csv";
+const RUN_CONFIG_FILE: &str = "run_config.ron";
+const LOG_FILE: &str = "log.txt";
+
+
+/// A parameter sweep, read from the file given to `batch <sweep.ron>`. Every point of the grid, or every random
+/// sample, is run once per seed
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SweepConfig {
+    pub base_config: Option<String>, // built in defaults when None
+    pub output_dir: String,
+    pub seeds: Vec<u64>,
+    pub grid: BTreeMap<String, Vec<f64>>, // "section.field": values, every combination is run
+    pub random: BTreeMap<String, (f64, f64)>, // "section.field": (min, max), drawn `samples` times
+    pub samples: usize,
+    pub sample_seed: u64,
+    pub time_limit_sec: f64, // every run also ends on the termination conditions of the base config, and on extinction
+    pub parallel: Option<usize>, // number of cores when None
+    pub record_replays: bool, // every run keeps its replay recording and checkpoints in memory, off by default
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self {
+            base_config: None,
+            output_dir: "output/batch".into(),
+            seeds: vec![0],
+            grid: BTreeMap::new(),
+            random: BTreeMap::new(),
+            samples: 1,
+            sample_seed: 0,
+            time_limit_sec: 600.,
+            parallel: None,
+            record_replays: false,
+        }
+    }
+}
+
+
+/// One simulation of the sweep
+struct Run {
+    index: usize,
+    seed: u64,
+    params: Vec<(String, f64)>,
+    dir: PathBuf,
+    config: SimConfig,
+}
+
+
+enum RunStatus {
+    Finished(RunOutcome),
+    Failed(String),
+}
+
+
+
+/// `batch <sweep.ron>`: run every simulation of the sweep headless, as many at a time as there are cores, each in
+/// its own process and results directory, then write a summary csv of the outcomes
+pub fn run_batch(args: &[String]) -> Result<(), String> {
+    let sweep_path = args.get(2).ok_or("usage: batch <sweep.ron>")?;
+    let text = fs::read_to_string(sweep_path).map_err(|e| format!("could not read {}: {}", sweep_path, e))?;
+    let sweep: SweepConfig = ron::from_str(&text).map_err(|e| format!("could not parse {}: {}", sweep_path, e))?;
+    if sweep.seeds.is_empty() {
+        return Err("the sweep needs at least one seed".into());
+    }
+
+    let base = match &sweep.base_config {
+        Some(path) => SimConfig::load(Path::new(path)).map_err(|errors| errors.join(", "))?,
+        None => SimConfig::default(),
+    };
+    let runs = plan_runs(&sweep, &base)?;
+    let exe = std::env::current_exe().map_err(|e| format!("could not find the simulation binary: {}", e))?;
+    let parallel = sweep.parallel
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, runs.len().max(1));
+    println!("Running {} simulations, {} at a time", runs.len(), parallel);
+
+    // every worker takes the next run that hasn't started yet
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<RunStatus>>> = Mutex::new(runs.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..parallel {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(run) = runs.get(i) else { break; };
+                let status = run_one(&exe, run);
+                match &status {
+                    RunStatus::Finished(outcome) => println!("run {}: {:?} at {:.0}s", run.index, outcome.cause, outcome.end_time),
+                    RunStatus::Failed(e) => println!("run {}: failed, {}", run.index, e),
+                }
+                results.lock().unwrap()[i] = Some(status);
+            });
+        }
+    });
+
+    let results: Vec<RunStatus> = results.into_inner().unwrap().into_iter()
+        .map(|status| status.unwrap_or(RunStatus::Failed("not run".into())))
+        .collect();
+    let summary = PathBuf::from(&sweep.output_dir).join(SUMMARY_FILE);
+    write_summary(&summary, &sweep, &runs, &results).map_err(|e| format!("could not write {:?}: {}", summary, e))?;
+    println!("Wrote {:?}", summary);
+    Ok(())
+}
+
+
+
+/// Every combination of the grid values, times `samples` random draws, times every seed
+fn plan_runs(sweep: &SweepConfig, base: &SimConfig) -> Result<Vec<Run>, String> {
+    let mut points: Vec<Vec<(String, f64)>> = vec![Vec::new()];
+    for (name, values) in sweep.grid.iter() {
+        points = points.iter()
+            .flat_map(|point| values.iter().map(move |v| {
+                let mut point = point.clone();
+                point.push((name.clone(), *v));
+                point
+            }))
+            .collect();
+    }
+
+    if !sweep.random.is_empty() {
+        let mut rng = ChaCha8Rng::seed_from_u64(sweep.sample_seed);
+        points = points.iter()
+            .flat_map(|point| (0..sweep.samples).map(|_| {
+                let mut point = point.clone();
+                for (name, (min, max)) in sweep.random.iter() {
+                    let v = if max > min { rng.gen_range(*min..*max) } else { *min };
+                    point.push((name.clone(), v));
+                }
+                point
+            }).collect::<Vec<_>>())
+            .collect();
+    }
+
+    let mut runs = Vec::new();
+    for params in points.iter() {
+        let mut config = base.clone();
+        for (name, value) in params.iter() {
+            config = set_config_value(&config, name, *value)?;
+        }
+        for seed in sweep.seeds.iter() {
+            let index = runs.len();
+            let dir = PathBuf::from(&sweep.output_dir).join(format!("run_{:04}", index));
+            let mut config = config.clone();
+            config.world.seed = Some(*seed);
+            config.world.output_dir = dir.to_string_lossy().into();
+            config.termination.time_limit_sec = Some(sweep.time_limit_sec);
+            config.termination.extinction = true;
+            config.termination.on_end = OnEnd::Exit;
+            config.replay.enabled = sweep.record_replays;
+            runs.push(Run { index, seed: *seed, params: params.clone(), dir, config });
+        }
+    }
+    Ok(runs)
+}
+
+
+
+fn run_one(exe: &Path, run: &Run) -> RunStatus {
+    if let Err(e) = fs::create_dir_all(&run.dir) {
+        return RunStatus::Failed(format!("could not create {:?}: {}", run.dir, e));
+    }
+    let config_path = run.dir.join(RUN_CONFIG_FILE);
+    let text = match ron::ser::to_string_pretty(&run.config, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(e) => return RunStatus::Failed(format!("could not serialize the config: {}", e)),
+    };
+    if let Err(e) = fs::write(&config_path, text) {
+        return RunStatus::Failed(format!("could not write {:?}: {}", config_path, e));
+    }
+    let log = match File::create(run.dir.join(LOG_FILE)) {
+        Ok(log) => log,
+        Err(e) => return RunStatus::Failed(format!("could not create the log: {}", e)),
+    };
+    let stderr = match log.try_clone() {
+        Ok(stderr) => stderr,
+        Err(e) => return RunStatus::Failed(format!("could not create the log: {}", e)),
+    };
+
+    let status = Command::new(exe)
+        .arg("--headless")
+        .arg("--config").arg(&config_path)
+        .stdin(Stdio::null())
+        .stdout(log)
+        .stderr(stderr)
+        .status();
+    match status {
+        Ok(status) if status.success() => {},
+        Ok(status) => return RunStatus::Failed(format!("exited with {}", status)),
+        Err(e) => return RunStatus::Failed(format!("could not start: {}", e)),
+    }
+
+    let outcome_path = RunOutcome::path(&run.config.world);
+    fs::read_to_string(&outcome_path)
+        .map_err(|e| format!("no outcome in {:?}: {}", outcome_path, e))
+        .and_then(|text| ron::from_str(&text).map_err(|e| format!("could not parse {:?}: {}", outcome_path, e)))
+        .map_or_else(RunStatus::Failed, RunStatus::Finished)
+}
+
+
+
+fn write_summary(path: &Path, sweep: &SweepConfig, runs: &[Run], results: &[RunStatus]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    // one survivor column per race, in the order the runs report them
+    let mut races: Vec<String> = Vec::new();
+    for result in results.iter() {
+        if let RunStatus::Finished(outcome) = result {
+            for (name, _) in outcome.survivors.iter() {
+                if !races.contains(name) { races.push(name.clone()); }
+            }
+        }
+    }
+    let params: Vec<&String> = sweep.grid.keys().chain(sweep.random.keys()).collect();
+
+    let mut header = vec!["run".to_string(), "seed".into()];
+    header.extend(params.iter().map(|p| p.to_string()));
+    header.extend(["status", "end_time", "cause", "winner", "survivors"].iter().map(|s| s.to_string()));
+    header.extend(races.iter().map(|r| format!("survivors_{}", r)));
+    header.extend(["mean_aggressiveness", "mean_avoidance", "mean_engineering", "mean_tribalism", "mean_reproducibility", "mean_fighting_score", "mean_lifespan"].iter().map(|s| s.to_string()));
+    writeln!(writer, "{}", header.join(","))?;
+
+    for (run, result) in runs.iter().zip(results.iter()) {
+        let mut row = vec![run.index.to_string(), run.seed.to_string()];
+        row.extend(run.params.iter().map(|(_, v)| v.to_string()));
+        match result {
+            RunStatus::Finished(outcome) => {
+                row.push("ok".into());
+                row.push(format!("{:.2}", outcome.end_time));
+                row.push(format!("{:?}", outcome.cause));
+                row.push(outcome.winner.clone().unwrap_or_default());
+                row.push(outcome.survivors.iter().map(|(_, n)| n).sum::<usize>().to_string());
+                for race in races.iter() {
+                    let n = outcome.survivors.iter().find(|(name, _)| name == race).map_or(0, |(_, n)| *n);
+                    row.push(n.to_string());
+                }
+                let m = &outcome.trait_means;
+                row.extend([m.aggressiveness, m.avoidance, m.engineering, m.tribalism, m.reproducibility, m.fighting_score, m.lifespan].iter().map(|v| format!("{:.4}", v)));
+            },
+            RunStatus::Failed(e) => {
+                // keep the csv one line per run, the reason is in the run's log
+                row.push(format!("failed: {}", e.replace(',', ";").replace('\n', " ")));
+            },
+        }
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()
+}
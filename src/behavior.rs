@@ -1,14 +1,53 @@
 use bevy::prelude::*;
-use crate::{debug_ui::*, species::*, food_source::*, food_desire::*, water_desire::*, water_source::*};
+use bevy::utils::HashSet;
+use crate::{debug_ui::*, species::*, food_source::*, food_desire::*, water_desire::*, water_source::*, weather::*};
+use crate::navigation::*;
+use crate::flow_field::FlowFields;
+use crate::fight::{Fight, FightConfig};
+use crate::health::{Health, HealthConfig};
+use crate::homebase::{Homebase, HomebaseConfig};
+use crate::engineering::{EngineeringConfig, ResourceNode};
 use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
 
 const MAX_VELOCITY: f32 = 2.;
-const MAX_ACCELERATION: f32 = 5.;
+const MAX_ACCELERATION: f32 = 1.;
+const STEERING_STRENGTH: f32 = 1.;
+const AVOID_DISTANCE: f32 = 200.;
+const PERCEPTION_RADIUS: f32 = 100.;
+
+
+/// The acceleration, steering and avoidance values are only the starting values of the debug menu sliders
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MovementConfig {
+    pub max_velocity: f32,
+    pub max_acceleration: f32,
+    pub steering_strength: f32,
+    pub avoid_other_strength: f32,
+    pub avoid_same_strength: f32,
+    pub avoid_distance: f32,
+    pub perception_radius: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_velocity: MAX_VELOCITY,
+            max_acceleration: MAX_ACCELERATION,
+            steering_strength: STEERING_STRENGTH,
+            avoid_other_strength: 0.,
+            avoid_same_strength: 0.,
+            avoid_distance: AVOID_DISTANCE,
+            perception_radius: PERCEPTION_RADIUS,
+        }
+    }
+}
 
 /*
 NOTES:
 
-- The velocity is always clamped to MAX_VELOCITY. 2.0 seems like a good value for the current setup.
+- The velocity is always clamped to max_velocity. 2.0 seems like a good value for the current setup.
 
 - The steering forces should not be clamped when they are added to the species. They should only be clamped at the
   end, when we are adding the new acceleration to the current velocity to get the new velocity. This way if there is one dominating
@@ -28,150 +67,264 @@ NOTES:
 
 
 
-pub fn behaviors(
-    mut food_source_query: Query<(Entity, &mut FoodSource)>,
-    mut water_source_query: Query<(Entity, &mut WaterSource)>,
-    mut species_set: ParamSet<(
-        Query<(&mut Transform, &mut Species, &mut FoodDesire, &mut WaterDesire)>,
-        Query<(&mut Transform, &mut Species)>,
-        Query<(Entity, &mut Species)>,
-    )>,
-    mut gizmos: Gizmos,
-    mut commands: Commands,
-    ui_state: ResMut<UiState>,
-    time: Res<Time>,
-) {
-
-    // species pair comparisons
-    let mut species_only = species_set.p2();
-    let mut combinations = species_only.iter_combinations_mut::<2>();
-    while let Some([mut this, other]) = combinations.fetch_next() {
-        let this_e = this.0;
-        let mut this_sp = this.1;
+/// What a species is steering towards this tick. Worked out for every species in parallel by `steer_species`, then
+/// acted on by `behaviors`
+#[derive(Component, Clone, Default)]
+pub struct Steering {
+    pub avoid_force: Vec3,
+    pub flee_force: Vec3, // away from the attacker, while losing a fight
+    pub acceleration: Vec3, // of the last tick, only kept for the debug drawing
+    pub food: Option<(Vec3, Vec3)>, // the nearest food and the direction to it, around obstacles
+    pub eat: Option<Entity>, // food in range
+    pub water: Option<(Vec3, Vec3)>, // the nearest water source and the direction to it
+    pub drink: Option<Entity>, // water in range
+    pub material: Option<(Vec3, Vec3)>, // the nearest resource node, for builders that are fed and watered
+    pub gather: Option<Entity>, // resource node in range
+    pub home_direction: Vec3,
+}
 
-        let other_e = other.0;
-        let other_sp = other.1;
 
-        let mut avoid_force = Vec3::ZERO;
-        let mut cohesion_force = Vec3::ZERO;
-        let other_to_this = this_sp.position - other_sp.position;
-        let distance = other_to_this.length();
-
-        // other race
-        if this_sp.race != other_sp.race {
-            // avoid other races. Scale perception radius by species avoidance value
-            if distance > 0. && distance < 200. {//this.perception_radius + this.avoidance {
-                avoid_force += ui_state.avoid_other_strength * other_to_this.normalize_or_zero();
-                this_sp.steering_forces += avoid_force;
-                if ui_state.show_physics_vectors {
-                    gizmos.ray(this_sp.position, avoid_force * ui_state.vector_scaling, Color::YELLOW);
-                }
-            }
 
+/// The read phase of the movement. Only looks at the world, so every species is handled in parallel
+pub fn steer_species(
+    mut species_query: Query<(&Species, &FoodDesire, &WaterDesire, &NavPath, Option<&Fight>, &mut Steering)>,
+    others: Query<&Species>,
+    food_source_query: Query<(Entity, &FoodSource)>,
+    water_source_query: Query<(Entity, &WaterSource)>,
+    home_query: Query<&Homebase>,
+    node_query: Query<(Entity, &ResourceNode)>,
+    ui_state: Res<UiState>,
+    weather: Res<Weather>,
+    weather_config: Res<WeatherConfig>,
+    movement: Res<MovementConfig>,
+    fight_config: Res<FightConfig>,
+    engineering: Res<EngineeringConfig>,
+    nav_grid: Res<NavGrid>,
+    flow_fields: Res<FlowFields>,
+) {
+    // storms reduce how far the species can see
+    let perception_factor = weather.perception_factor(&weather_config);
+    let avoid_distance = movement.avoid_distance * perception_factor;
+    let avoiding = ui_state.avoid_other_strength != 0. || ui_state.avoid_same_strength != 0.;
 
-        // same race
-        } else {
+    species_query.par_iter_mut().for_each(|(sp, food_des, water_des, path, fight, mut steering)| {
 
-            // avoid species of the same race 
-            if distance > 0. && distance <  200. {//this.perception_radius {
-                avoid_force += ui_state.avoid_same_strength * other_to_this.normalize_or_zero();
-                this_sp.steering_forces += avoid_force;
+        // avoid the other races and the own race, each with its own strength
+        let mut avoid_force = Vec3::ZERO;
+        if avoiding {
+            for other in others.iter() {
+                let other_to_this = sp.position - other.position;
+                let distance = other_to_this.length();
+                if distance <= 0. || distance >= avoid_distance { continue; }
+
+                let strength = if sp.race != other.race { ui_state.avoid_other_strength } else { ui_state.avoid_same_strength };
+                avoid_force += strength * other_to_this.normalize_or_zero();
             }
         }
-    }
-
+        steering.avoid_force = avoid_force;
 
-    // steer towards food and water
-    for (
-        mut tf,
-        mut sp, 
-        mut food_des, 
-        mut water_des
-    ) in species_set.p0().iter_mut() {
-
-        // steer towards food
-        let mut min_distance: f32 = 1000000.0;
-        let mut food_force = Vec3::ZERO;
-        for (food_source_e, food_source) in food_source_query.iter_mut() {
-
-            // don't search if food_desire is greater than 0.
-            if food_des.val > 0. { break; }
-            // if food_des.timer.percent_left() > food_des.grace_period_percent { break };
-
-            let species_to_target = food_source.position.xy() - sp.position.xy();
-            let distance = species_to_target.length();
-
-            // make sure food entity still exists before species goes to it
-            if let Some(_) = commands.get_entity(food_source_e) { 
-                //TODO case where there is no food, and as soon as it spawns all species steer
-                //towards it. Make it so they steer towards it only if food is within their perception radius? Then the
-                //species might never see the food
-                //if distance < min_distance && distance < sp.perception_radius {
+        // steer towards homebase. If other behaviors are close to 0, this one will dominate, even though it has no strength factor
+        // TODO add strength factor? Maybe increase strength when the species health is low, or it has no food, water, etc.
+        // Nomads have nowhere to go back to
+        steering.home_direction = match sp.homebase {
+            Some(homebase) => flow_fields.direction(&nav_grid, sp.position, homebase)
+                .unwrap_or_else(|| path.steer_direction(sp.position, homebase)),
+            None => Vec3::ZERO,
+        };
+        let home = home_query.iter().find(|home| home.is_home_of(sp));
+        let home_distance = home.map_or(f32::INFINITY, |home| (home.position.xy() - sp.position.xy()).length());
+
+        steering.flee_force = match fight.and_then(|fight| fight.flee_from) {
+            Some(attacker) => fight_config.flee_strength * (sp.position - attacker).normalize_or_zero(),
+            None => Vec3::ZERO,
+        };
+
+
+        // steer towards food the species can see. Only searched for when food_desire is 0 or below
+        let mut min_distance: f32 = sp.perception_radius * perception_factor;
+        let mut food_target: Option<Vec3> = None;
+        steering.eat = None;
+        if food_des.val <= 0. {
+            for (food_source_e, food_source) in food_source_query.iter() {
+                let distance = (food_source.position.xy() - sp.position.xy()).length();
                 if distance < min_distance {
-                    let acc_vec2 = species_to_target.normalize_or_zero() * food_des.val.abs();
-
-                    food_force = Vec3::new(acc_vec2.x, acc_vec2.y, 0.);
+                    food_target = Some(food_source.position);
                     min_distance = distance;
                 }
-                // eat if within range
+                // eat if within range, after that it isn't hungry anymore
                 if distance < food_des.in_range_eat {
-                    food_des.val += food_source.value;
-                    commands.entity(food_source_e).despawn();
+                    steering.eat = Some(food_source_e);
+                    break;
                 }
-            } else { // if it doesn't exist, just move on to the next food source
-                continue;
             }
         }
-        sp.steering_forces += food_force;
+        // go around obstacles on the way to the food, unless the stockpile at home is closer. The way home is known
+        // even when it is out of sight
+        steering.food = food_target.map(|target| (target, path.steer_direction(sp.position, target)));
+        let food_seen = food_target.is_some();
+        if let Some(home) = home.filter(|home| food_des.val <= 0. && home.food > 0. && (!food_seen || home_distance < min_distance)) {
+            steering.food = Some((home.position, steering.home_direction));
+        }
 
 
-        // steer to water sources
+        // steer to water sources. They don't move, so unlike food they are known from anywhere
         min_distance = 10000000.0;
-        let mut water_force = Vec3::ZERO;
-        for (water_source_e, mut water_source) in water_source_query.iter_mut() {
-            // if water_des.timer.percent_left() > water_des.grace_period_percent { break; };
-            if water_des.val > 0. { break; }
+        let mut water_target: Option<Vec3> = None;
+        steering.drink = None;
+        if water_des.val <= 0. {
+            for (water_source_e, water_source) in water_source_query.iter() {
+                let distance = (water_source.position.xy() - sp.position.xy()).length();
+                if distance < min_distance {
+                    water_target = Some(water_source.position);
+                    min_distance = distance;
+                }
+                // drink, as long as the source has not dried up
+                if steering.drink.is_none() && distance < water_des.in_range_drink && water_source.value > 0. {
+                    steering.drink = Some(water_source_e);
+                }
+            }
+        }
+        // every species heading to the same water source shares its flow field
+        steering.water = water_target.map(|target| {
+            let direction = flow_fields.direction(&nav_grid, sp.position, target)
+                .unwrap_or_else(|| path.steer_direction(sp.position, target));
+            (target, direction)
+        });
+        if let Some(home) = home.filter(|home| water_des.val <= 0. && home.water > 0. && home_distance < min_distance) {
+            steering.water = Some((home.position, steering.home_direction));
+        }
 
-            let species_to_target = water_source.position.xy() - sp.position.xy();
-            let distance = species_to_target.length();
+        // builders with nothing better to do fetch wood and stone from around their homebase
+        steering.material = None;
+        steering.gather = None;
+        let idle = food_des.val > 0. && water_des.val > 0. && sp.carrying.is_none();
+        if let Some(homebase) = sp.homebase.filter(|_| idle && engineering.is_builder(sp)) {
+            let nearest = node_query.iter()
+                .filter(|(_, node)| (node.position.xy() - homebase.xy()).length() < engineering.forage_radius)
+                .map(|(e, node)| (e, node, (node.position.xy() - sp.position.xy()).length()))
+                .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+            if let Some((node_e, node, distance)) = nearest {
+                steering.material = Some((node.position, path.steer_direction(sp.position, node.position)));
+                if distance < engineering.gather_range {
+                    steering.gather = Some(node_e);
+                }
+            }
+        }
+    });
+}
 
-            if let Some(_) = commands.get_entity(water_source_e) {
 
-                if distance < min_distance {
-                    // let max_steer = species_to_target.normalize_or_zero() * 10.;
-                    // let acc_vec2 = (species_to_target.normalize_or_zero()).lerp(max_steer, water_des.timer.percent());
-                    let acc_vec2 = species_to_target.normalize_or_zero() * water_des.val.abs();
 
-                    water_force = Vec3::new(acc_vec2.x, acc_vec2.y, 0.);
-                    min_distance = distance;
+/// The apply phase of the movement. Eating and drinking change the sources every species shares, so they happen one
+/// species at a time. The physics only touch the species itself and run in parallel again
+pub fn behaviors(
+    mut species_query: Query<(&mut Transform, &mut Species, &mut FoodDesire, &mut WaterDesire, &mut Steering, &Health)>,
+    food_source_query: Query<&FoodSource>,
+    mut water_source_query: Query<&mut WaterSource>,
+    mut node_query: Query<&mut ResourceNode>,
+    mut commands: Commands,
+    ui_state: Res<UiState>,
+    weather: Res<Weather>,
+    weather_config: Res<WeatherConfig>,
+    movement: Res<MovementConfig>,
+    aging: Res<AgingConfig>,
+    metabolism: Res<MetabolismConfig>,
+    health_config: Res<HealthConfig>,
+    homebase: Res<HomebaseConfig>,
+    engineering: Res<EngineeringConfig>,
+    nav_grid: Res<NavGrid>,
+    time: Res<Time>,
+) {
+    // storms reduce how fast the species move
+    let max_velocity = movement.max_velocity * weather.movement_factor(&weather_config);
+    let delta = time.delta_seconds();
+
+    // the food is only despawned at the end of the tick, don't let two species eat the same one
+    let mut eaten: HashSet<Entity> = HashSet::new();
+    for (_, mut sp, mut food_des, mut water_des, steering, _) in species_query.iter_mut() {
+        sp.steering_forces += steering.avoid_force + steering.flee_force;
+
+        // eat, then steer towards food if still hungry
+        if let Some(food_source_e) = steering.eat {
+            if let Ok(food_source) = food_source_query.get(food_source_e) {
+                if eaten.insert(food_source_e) {
+                    food_des.val += food_source.value;
+                    commands.entity(food_source_e).despawn();
+                }
+            }
+        }
+        let mut food_target: Option<Vec3> = None;
+        if food_des.val <= 0. {
+            if let Some((target, direction)) = steering.food {
+                sp.steering_forces += direction * food_des.val.abs();
+                food_target = Some(target);
+            }
+        }
+
+        // drink, then steer towards water if still thirsty
+        let mut water_target: Option<Vec3> = None;
+        if water_des.val <= 0. {
+            if let Some(water_source_e) = steering.drink {
+                if let Ok(mut water_source) = water_source_query.get_mut(water_source_e) {
+                    // another species may have emptied it this tick
+                    if water_source.value > 0. {
+                        sp.velocity *= 0.95;
+                        water_des.is_consuming = true;
+                        let val = water_des.drink_rate_hz * delta;
+                        water_des.val += val;
+                        water_source.value -= val;
+
+                        // done drinking, take some along for the homebase
+                        if water_des.val > 0. {
+                            let carry = (homebase.carry_water - water_des.carried).min(water_source.value).max(0.);
+                            water_des.carried += carry;
+                            water_source.value -= carry;
+                        }
+                    }
                 }
-                // drink
-                if distance < water_des.in_range_drink {
-                    sp.velocity *= 0.95;
-                    water_des.is_consuming = true;
-                    let val = water_des.drink_rate_hz * time.delta_seconds();
-                    water_des.val += val;
-                    water_source.value -= val;
-                } 
-                // if it is greater than 0, then start over so it has a grace period by setting value to capacity
-                if water_des.val > 0. {
-                    water_des.val = water_des.spawn_val;
-                    min_distance = 1000000.0;
+            }
+            // if it is greater than 0, then start over so it has a grace period by setting value to capacity
+            if water_des.val > 0. {
+                water_des.val = water_des.spawn_val;
+            } else if let Some((target, direction)) = steering.water {
+                sp.steering_forces += direction * water_des.val.abs();
+                water_target = Some(target);
+            }
+        }
+
+        // gather, or head for the building material
+        let mut material_target: Option<Vec3> = None;
+        if let Some(node_e) = steering.gather {
+            if let Ok(mut node) = node_query.get_mut(node_e) {
+                // another species may have used it up this tick
+                if node.amount > 0. && sp.carrying.is_none() {
+                    let amount = engineering.carry_amount.min(node.amount);
+                    node.amount -= amount;
+                    sp.carrying = Some((node.material, amount));
+                    food_des.val -= metabolism.gather_cost;
+                    if node.amount <= 0. {
+                        commands.entity(node_e).despawn();
+                    }
                 }
             }
         }
-        sp.steering_forces += water_force;
+        if sp.carrying.is_none() {
+            if let Some((target, direction)) = steering.material {
+                sp.steering_forces += direction * engineering.gather_strength;
+                material_target = Some(target);
+            }
+        }
 
-        // steer towards homebase. If other behaviors are close to 0, this one will dominate, even though it has no strength factor
-        // TODO add strength factor? Maybe increase strength when the species health is low, or it has no food, water, etc.
-        let steering_homebase = (sp.homebase - sp.position).normalize_or_zero();
-        sp.steering_forces += steering_homebase;
+        sp.steering_forces += steering.home_direction;
+
+        // the path is computed towards the most urgent target: water, then food, then building material, then home.
+        // Targets with a flow field don't need one
+        sp.target_pos = water_target.or(food_target).or(material_target).or(sp.homebase).unwrap_or(sp.position);
     }
 
 
     // update species physics
-    for (mut tf, mut sp) in species_set.p1().iter_mut() {
-        // update species physics
+    species_query.par_iter_mut().for_each(|(mut tf, mut sp, mut food_des, _, mut steering, health)| {
         let mut cur_acc = sp.acceleration;
         let mut cur_vel = sp.velocity;
         let mut cur_pos = sp.position;
@@ -185,7 +338,7 @@ pub fn behaviors(
             cur_vel = Vec3::ZERO;
         }
         if cur_pos.is_nan() {
-            cur_pos = sp.homebase;
+            cur_pos = sp.homebase.unwrap_or(Vec3::ZERO);
         }
 
         if ui_state.steering_strength != 0. {
@@ -194,7 +347,7 @@ pub fn behaviors(
             sp.steering_forces = sp.steering_forces.clamp_length_max(1.0);
         }
 
-        let mut new_acc = (cur_acc + sp.steering_forces) * time.delta_seconds();
+        let mut new_acc = (cur_acc + sp.steering_forces) * delta;
 
         if ui_state.max_acceleration != 0. {
             new_acc = new_acc.clamp_length_max(ui_state.max_acceleration);
@@ -204,21 +357,35 @@ pub fn behaviors(
 
         let mut new_vel = cur_vel + new_acc;
 
-        new_vel = new_vel.clamp_length_max(MAX_VELOCITY);
+        // juveniles can't keep up with the adults, neither can a limping species
+        let max_velocity = if sp.stage(&aging) == LifeStage::Juvenile { max_velocity * aging.juvenile_speed } else { max_velocity };
+        let max_velocity = max_velocity * health.speed_factor(&health_config);
+        new_vel = new_vel.clamp_length_max(max_velocity);
         
-        let new_pos = cur_pos + new_vel;
-
-        // acceleration is always smaller than velocity, so scale even more
-        if ui_state.show_physics_vectors {
-            gizmos.ray(new_pos, new_vel * ui_state.vector_scaling, Color::GREEN);
-            gizmos.ray(new_pos, new_acc * ui_state.vector_scaling, Color::RED);
-        }
-        if ui_state.show_perception_radius {
-            gizmos.circle(new_pos, Vec3::Z, sp.perception_radius, Color::WHITE);
+        let mut new_pos = cur_pos + new_vel;
+
+        // don't walk into obstacles. Slide along them by keeping whichever part of the move is still free. A species
+        // that a structure was built on top of can walk out of it
+        if nav_grid.is_blocked_world(new_pos.xy()) && !nav_grid.is_blocked_world(cur_pos.xy()) {
+            let slide_x = Vec3::new(new_pos.x, cur_pos.y, cur_pos.z);
+            let slide_y = Vec3::new(cur_pos.x, new_pos.y, cur_pos.z);
+            if !nav_grid.is_blocked_world(slide_x.xy()) {
+                new_pos = slide_x;
+                new_vel.y = 0.;
+            } else if !nav_grid.is_blocked_world(slide_y.xy()) {
+                new_pos = slide_y;
+                new_vel.x = 0.;
+            } else {
+                new_pos = cur_pos;
+                new_vel = Vec3::ZERO;
+            }
         }
 
+        // speeding up and slowing down both take energy
+        food_des.val -= metabolism.acceleration_cost * (new_vel - cur_vel).length();
 
         // now set species physics data
+        steering.acceleration = new_acc;
         sp.velocity = new_vel;
         sp.position = new_pos;
 
@@ -229,13 +396,31 @@ pub fn behaviors(
         // subtracting PI/2 makes the sprite in line with y axis, travels facing the top
         // not subtracting makes it in line with x axis, travels facing the side
         tf.rotation = Quat::from_euler(EulerRot::XYZ, 0., 0., angle - PI/2.);
-    }
-
-
-
+    });
+}
 
 
 
+/// Debug drawing of the species physics. Runs every frame rather than in the fixed simulation step,
+/// gizmos drawn from `FixedUpdate` only show up on the frames that happened to run a step
+pub fn draw_species_gizmos(
+    query: Query<(&Species, &Steering)>,
+    ui_state: Res<UiState>,
+    weather: Res<Weather>,
+    weather_config: Res<WeatherConfig>,
+    mut gizmos: Gizmos,
+) {
+    let perception_factor = weather.perception_factor(&weather_config);
+    for (sp, steering) in query.iter() {
+        if ui_state.show_physics_vectors {
+            if steering.avoid_force != Vec3::ZERO {
+                gizmos.ray(sp.position, steering.avoid_force * ui_state.vector_scaling, Color::YELLOW);
+            }
+            gizmos.ray(sp.position, sp.velocity * ui_state.vector_scaling, Color::GREEN);
+            gizmos.ray(sp.position, steering.acceleration * ui_state.vector_scaling, Color::RED);
+        }
+        if ui_state.show_perception_radius {
+            gizmos.circle(sp.position, Vec3::Z, sp.perception_radius * perception_factor, Color::WHITE);
+        }
+    }
 }
-
-
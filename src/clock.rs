@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The simulation runs in `FixedUpdate` at this rate, so every run takes exactly the same steps
+pub const SIM_TICK_HZ: f64 = 60.;
+
+
+/// Every system that changes the simulation. They run chained in `FixedUpdate`, so every run with the same seed
+/// and inputs goes through the same steps in the same order
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimSet;
+
+
+/// The steps of a simulation tick, in the order they run. Each one belongs to a single plugin, which chains its own
+/// systems inside it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SimStep {
+    Inputs,
+    Needs,
+    Reproduction,
+    Environment,
+    Movement,
+    Colony,
+    Combat,
+    Bookkeeping,
+}
+
+
+/// Simulated time since the run started, and the number of ticks so far. Unlike `Time` it is saved in snapshots, so a resumed run continues
+/// from where it was saved instead of starting again at 0
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SimClock {
+    pub elapsed: f64,
+    pub frame: u64,
+}
+
+impl SimClock {
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed as f32
+    }
+}
+
+
+
+pub fn tick_sim_clock(
+    mut clock: ResMut<SimClock>,
+    time: Res<Time>,
+) {
+    clock.elapsed += time.delta_seconds_f64();
+    clock.frame += 1;
+}
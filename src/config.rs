@@ -0,0 +1,559 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::behavior::MovementConfig;
+use crate::fight::FightConfig;
+use crate::food_desire::{HungerConfig, MetabolismConfig};
+use crate::food_source::FoodConfig;
+use crate::health::HealthConfig;
+use crate::engineering::EngineeringConfig;
+use crate::homebase::HomebaseConfig;
+use crate::obstacle::ObstacleConfig;
+use crate::reproduce::ReproductionConfig;
+use crate::statistics::StatisticsConfig;
+use crate::water_desire::ThirstConfig;
+use crate::water_source::WaterConfig;
+use crate::weather::WeatherConfig;
+use crate::autosave::AutosaveConfig;
+use crate::replay::*;
+use crate::termination::TerminationConfig;
+use crate::debug_ui::UiState;
+use crate::species::{AgingConfig, Species};
+use crate::food_desire::FoodDesire;
+use crate::water_desire::WaterDesire;
+use crate::statistics::Statistics;
+use crate::sim_rng::SimRng;
+use crate::snapshot::ResumeFrom;
+
+pub const DEFAULT_CONFIG_PATH: &str = "assets/config/sim.ron";
+const EFFECTIVE_CONFIG_FILE: &str = "config.ron";
+const WATCH_INTERVAL_SEC: f32 = 1.0;
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub map_width: f32,
+    pub map_height: f32,
+    pub races_file: String,
+    pub output_dir: String,
+    pub seed: Option<u64>, // random seed when None
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            map_width: 4000.,
+            map_height: 4000.,
+            races_file: "assets/config/races.ron".into(),
+            output_dir: "output".into(),
+            seed: None,
+        }
+    }
+}
+
+
+/// Every tunable value of the simulation. Each section is also inserted as its own resource, so systems only ask
+/// for the part they use. Anything left out of the file keeps its default value
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub world: WorldConfig,
+    pub movement: MovementConfig,
+    pub hunger: HungerConfig,
+    pub metabolism: MetabolismConfig,
+    pub thirst: ThirstConfig,
+    pub health: HealthConfig,
+    pub fight: FightConfig,
+    pub reproduction: ReproductionConfig,
+    pub aging: AgingConfig,
+    pub homebase: HomebaseConfig,
+    pub engineering: EngineeringConfig,
+    pub food: FoodConfig,
+    pub water: WaterConfig,
+    pub weather: WeatherConfig,
+    pub obstacles: ObstacleConfig,
+    pub statistics: StatisticsConfig,
+    pub autosave: AutosaveConfig,
+    pub replay: ReplayConfig,
+    pub termination: TerminationConfig,
+}
+
+
+/// Where the config was loaded from, None when running on the defaults
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ConfigPath(pub Option<PathBuf>);
+
+
+impl SimConfig {
+    pub fn load(path: &Path) -> Result<Self, Vec<String>> {
+        let text = fs::read_to_string(path).map_err(|e| vec![format!("could not read {:?}: {}", path, e)])?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Vec<String>> {
+        let config: SimConfig = ron::from_str(text).map_err(|e| vec![format!("could not parse the config: {}", e)])?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Collect every bad value instead of stopping at the first one
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut positive = |name: &str, value: f32| {
+            if !(value > 0.) { errors.push(format!("{} must be greater than 0, got {}", name, value)); }
+        };
+        positive("world.map_width", self.world.map_width);
+        positive("world.map_height", self.world.map_height);
+        positive("movement.max_velocity", self.movement.max_velocity);
+        positive("movement.max_acceleration", self.movement.max_acceleration);
+        positive("movement.steering_strength", self.movement.steering_strength);
+        positive("movement.perception_radius", self.movement.perception_radius);
+        positive("hunger.max_hunger", self.hunger.max_hunger);
+        positive("hunger.eat_radius", self.hunger.eat_radius);
+        positive("thirst.max_water", self.thirst.max_water);
+        positive("thirst.drink_range", self.thirst.drink_range);
+        positive("thirst.drink_rate_hz", self.thirst.drink_rate_hz);
+        positive("health.max_health", self.health.max_health);
+        positive("health.blood_fade_time", self.health.blood_fade_time);
+        positive("health.health_bar_zoom", self.health.health_bar_zoom);
+        positive("fight.attack_range", self.fight.attack_range);
+        positive("aging.elder_fraction", self.aging.elder_fraction);
+        positive("aging.juvenile_scale", self.aging.juvenile_scale);
+        positive("aging.juvenile_speed", self.aging.juvenile_speed);
+        positive("homebase.radius", self.homebase.radius);
+        positive("homebase.max_health", self.homebase.max_health);
+        positive("engineering.ring_radius", self.engineering.ring_radius);
+        positive("engineering.wall_health", self.engineering.wall_health);
+        positive("engineering.tower_health", self.engineering.tower_health);
+        positive("engineering.storage_health", self.engineering.storage_health);
+        positive("food.spawn_spread", self.food.spawn_spread);
+        positive("water.capacity", self.water.capacity);
+        positive("statistics.population_sample_sec", self.statistics.population_sample_sec);
+        positive("autosave.interval_min", self.autosave.interval_min);
+        positive("replay.checkpoint_interval_sec", self.replay.checkpoint_interval_sec);
+        positive("replay.fast_forward_speed", self.replay.fast_forward_speed);
+        if let Some(limit) = self.termination.time_limit_sec { positive("termination.time_limit_sec", limit as f32); }
+        if let Some(threshold) = self.termination.trait_convergence { positive("termination.trait_convergence", threshold); }
+
+        let mut non_negative = |name: &str, value: f32| {
+            if !(value >= 0.) { errors.push(format!("{} can not be negative, got {}", name, value)); }
+        };
+        non_negative("movement.avoid_other_strength", self.movement.avoid_other_strength);
+        non_negative("movement.avoid_same_strength", self.movement.avoid_same_strength);
+        non_negative("movement.avoid_distance", self.movement.avoid_distance);
+        non_negative("hunger.hunger_rate_hz", self.hunger.hunger_rate_hz);
+        non_negative("metabolism.speed_cost", self.metabolism.speed_cost);
+        non_negative("metabolism.acceleration_cost", self.metabolism.acceleration_cost);
+        non_negative("metabolism.perception_cost", self.metabolism.perception_cost);
+        non_negative("metabolism.size_cost", self.metabolism.size_cost);
+        non_negative("metabolism.hit_cost", self.metabolism.hit_cost);
+        non_negative("metabolism.reproduction_cost", self.metabolism.reproduction_cost);
+        non_negative("metabolism.gather_cost", self.metabolism.gather_cost);
+        non_negative("thirst.thirst_rate_hz", self.thirst.thirst_rate_hz);
+        non_negative("health.low_water_damage_rate", self.health.low_water_damage_rate);
+        non_negative("health.low_hunger_damage_rate", self.health.low_hunger_damage_rate);
+        non_negative("health.regen_rate_hz", self.health.regen_rate_hz);
+        non_negative("health.home_radius", self.health.home_radius);
+        non_negative("health.home_regen_factor", self.health.home_regen_factor);
+        non_negative("health.injury_sec", self.health.injury_sec);
+        non_negative("health.injury_speed", self.health.injury_speed);
+        non_negative("health.injury_attack", self.health.injury_attack);
+        non_negative("fight.species_food_eat_restore", self.fight.species_food_eat_restore);
+        non_negative("fight.hit_damage", self.fight.hit_damage);
+        non_negative("fight.hit_cooldown_sec", self.fight.hit_cooldown_sec);
+        non_negative("fight.flee_health", self.fight.flee_health);
+        non_negative("fight.flee_sec", self.fight.flee_sec);
+        non_negative("fight.flee_strength", self.fight.flee_strength);
+        non_negative("reproduction.grace_period_sec", self.reproduction.grace_period_sec);
+        non_negative("aging.maturity_sec", self.aging.maturity_sec);
+        non_negative("aging.elder_decline_rate", self.aging.elder_decline_rate);
+        non_negative("homebase.food_capacity", self.homebase.food_capacity);
+        non_negative("homebase.water_capacity", self.homebase.water_capacity);
+        non_negative("homebase.carry_water", self.homebase.carry_water);
+        non_negative("homebase.site_clearance", self.homebase.site_clearance);
+        non_negative("engineering.node_amount", self.engineering.node_amount);
+        non_negative("engineering.node_clearance", self.engineering.node_clearance);
+        non_negative("engineering.forage_radius", self.engineering.forage_radius);
+        non_negative("engineering.gather_range", self.engineering.gather_range);
+        non_negative("engineering.gather_strength", self.engineering.gather_strength);
+        non_negative("engineering.carry_amount", self.engineering.carry_amount);
+        non_negative("engineering.build_interval_sec", self.engineering.build_interval_sec);
+        non_negative("engineering.wall_stone", self.engineering.wall_stone);
+        non_negative("engineering.tower_wood", self.engineering.tower_wood);
+        non_negative("engineering.tower_stone", self.engineering.tower_stone);
+        non_negative("engineering.storage_wood", self.engineering.storage_wood);
+        non_negative("engineering.tower_range", self.engineering.tower_range);
+        non_negative("engineering.tower_damage", self.engineering.tower_damage);
+        non_negative("engineering.tower_cooldown_sec", self.engineering.tower_cooldown_sec);
+        non_negative("engineering.storage_capacity", self.engineering.storage_capacity);
+        non_negative("food.replenish_chance", self.food.replenish_chance);
+        non_negative("food.wither_rate_hz", self.food.wither_rate_hz);
+        non_negative("food.corpse_value", self.food.corpse_value);
+        non_negative("food.corpse_decay_rate_hz", self.food.corpse_decay_rate_hz);
+        non_negative("water.refill_rate_hz", self.water.refill_rate_hz);
+        non_negative("weather.drought_per_min", self.weather.drought_per_min);
+        non_negative("weather.flood_per_min", self.weather.flood_per_min);
+        non_negative("weather.storm_per_min", self.weather.storm_per_min);
+        non_negative("weather.drought_duration", self.weather.drought_duration);
+        non_negative("weather.flood_duration", self.weather.flood_duration);
+        non_negative("weather.storm_duration", self.weather.storm_duration);
+        non_negative("weather.storm_damage_rate", self.weather.storm_damage_rate);
+        non_negative("homebase.split_cooldown_sec", self.homebase.split_cooldown_sec);
+        non_negative("obstacles.homebase_clearance", self.obstacles.homebase_clearance);
+        non_negative("obstacles.water_clearance", self.obstacles.water_clearance);
+
+        if !(0.0..1.0).contains(&self.reproduction.min_reproduce_threshold) {
+            errors.push(format!("reproduction.min_reproduce_threshold must be between 0 and 1, got {}", self.reproduction.min_reproduce_threshold));
+        }
+        if !(0.0..1.0).contains(&self.reproduction.mutation) {
+            errors.push(format!("reproduction.mutation must be between 0 and 1, got {}", self.reproduction.mutation));
+        }
+        if !(0.0..=1.0).contains(&self.health.injury_chance) {
+            errors.push(format!("health.injury_chance must be between 0 and 1, got {}", self.health.injury_chance));
+        }
+        if self.homebase.colony_size < 1 || self.homebase.colony_size > self.homebase.crowding_population {
+            errors.push(format!("homebase.colony_size must be between 1 and homebase.crowding_population ({}), got {}", self.homebase.crowding_population, self.homebase.colony_size));
+        }
+        if self.food.replenish_min > self.food.replenish_max {
+            errors.push(format!("food.replenish_min ({}) is larger than food.replenish_max ({})", self.food.replenish_min, self.food.replenish_max));
+        }
+        if self.food.n_food_max < 2 {
+            errors.push(format!("food.n_food_max must be at least 2, got {}", self.food.n_food_max));
+        }
+        if self.termination.population_cap == Some(0) {
+            errors.push("termination.population_cap must be at least 1".into());
+        }
+        if self.autosave.keep < 1 {
+            errors.push("autosave.keep must be at least 1".into());
+        }
+        if self.replay.max_checkpoints < 2 {
+            errors.push("replay.max_checkpoints must be at least 2".into());
+        }
+        if self.world.races_file.is_empty() {
+            errors.push("world.races_file is empty".into());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Insert the config and every one of its sections as resources
+    pub fn insert_resources(&self, app: &mut App) {
+        app.insert_resource(self.clone())
+            .insert_resource(self.world.clone())
+            .insert_resource(self.movement.clone())
+            .insert_resource(self.hunger.clone())
+            .insert_resource(self.metabolism.clone())
+            .insert_resource(self.thirst.clone())
+            .insert_resource(self.health.clone())
+            .insert_resource(self.fight.clone())
+            .insert_resource(self.reproduction.clone())
+            .insert_resource(self.aging.clone())
+            .insert_resource(self.homebase.clone())
+            .insert_resource(self.engineering.clone())
+            .insert_resource(self.food.clone())
+            .insert_resource(self.water.clone())
+            .insert_resource(self.weather.clone())
+            .insert_resource(self.obstacles.clone())
+            .insert_resource(self.statistics.clone())
+            .insert_resource(self.autosave.clone())
+            .insert_resource(self.replay.clone())
+            .insert_resource(self.termination.clone());
+    }
+
+    /// Same as `insert_resources`, for a simulation that is already running
+    pub fn apply(&self, commands: &mut Commands) {
+        commands.insert_resource(self.world.clone());
+        commands.insert_resource(self.movement.clone());
+        commands.insert_resource(self.hunger.clone());
+        commands.insert_resource(self.metabolism.clone());
+        commands.insert_resource(self.thirst.clone());
+        commands.insert_resource(self.health.clone());
+        commands.insert_resource(self.fight.clone());
+        commands.insert_resource(self.reproduction.clone());
+        commands.insert_resource(self.aging.clone());
+        commands.insert_resource(self.homebase.clone());
+        commands.insert_resource(self.engineering.clone());
+        commands.insert_resource(self.food.clone());
+        commands.insert_resource(self.water.clone());
+        commands.insert_resource(self.weather.clone());
+        commands.insert_resource(self.obstacles.clone());
+        commands.insert_resource(self.statistics.clone());
+        commands.insert_resource(self.autosave.clone());
+        commands.insert_resource(self.replay.clone());
+        commands.insert_resource(self.termination.clone());
+        commands.insert_resource(self.clone());
+    }
+
+    /// Insert only the sections that differ from `old`, so the resources that were changed at runtime, like the
+    /// weather from the debug menu, keep their values unless the file changed them too
+    pub fn apply_changed(&self, old: &SimConfig, commands: &mut Commands) {
+        if self.world != old.world { commands.insert_resource(self.world.clone()); }
+        if self.movement != old.movement { commands.insert_resource(self.movement.clone()); }
+        if self.hunger != old.hunger { commands.insert_resource(self.hunger.clone()); }
+        if self.metabolism != old.metabolism { commands.insert_resource(self.metabolism.clone()); }
+        if self.thirst != old.thirst { commands.insert_resource(self.thirst.clone()); }
+        if self.health != old.health { commands.insert_resource(self.health.clone()); }
+        if self.fight != old.fight { commands.insert_resource(self.fight.clone()); }
+        if self.reproduction != old.reproduction { commands.insert_resource(self.reproduction.clone()); }
+        if self.aging != old.aging { commands.insert_resource(self.aging.clone()); }
+        if self.homebase != old.homebase { commands.insert_resource(self.homebase.clone()); }
+        if self.engineering != old.engineering { commands.insert_resource(self.engineering.clone()); }
+        if self.food != old.food { commands.insert_resource(self.food.clone()); }
+        if self.water != old.water { commands.insert_resource(self.water.clone()); }
+        if self.weather != old.weather { commands.insert_resource(self.weather.clone()); }
+        if self.obstacles != old.obstacles { commands.insert_resource(self.obstacles.clone()); }
+        if self.statistics != old.statistics { commands.insert_resource(self.statistics.clone()); }
+        if self.autosave != old.autosave { commands.insert_resource(self.autosave.clone()); }
+        if self.replay != old.replay { commands.insert_resource(self.replay.clone()); }
+        if self.termination != old.termination { commands.insert_resource(self.termination.clone()); }
+        commands.insert_resource(self.clone());
+    }
+}
+
+
+
+/// Checks the config file for changes once in a while so it can be applied without restarting
+#[derive(Resource)]
+pub struct ConfigWatcher {
+    pub timer: Timer,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl FromWorld for ConfigWatcher {
+    fn from_world(world: &mut World) -> Self {
+        let last_modified = world.resource::<ConfigPath>().0.as_deref().and_then(modified_time);
+        Self {
+            timer: Timer::from_seconds(WATCH_INTERVAL_SEC, TimerMode::Repeating),
+            last_modified,
+        }
+    }
+}
+
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+
+/// Every value that differs between the two configs, as "section.field: old -> new"
+pub fn config_changes(old: &SimConfig, new: &SimConfig) -> Vec<String> {
+    let mut changes = Vec::new();
+    let (Ok(old), Ok(new)) = (to_value(old), to_value(new)) else { return changes; };
+    diff_values("", &old, &new, &mut changes);
+    changes
+}
+
+
+fn to_value(config: &SimConfig) -> Result<ron::Value, ron::Error> {
+    let text = ron::to_string(config)?;
+    Ok(ron::from_str(&text)?)
+}
+
+
+fn diff_values(prefix: &str, old: &ron::Value, new: &ron::Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (ron::Value::Map(old_map), ron::Value::Map(new_map)) => {
+            for (key, old_field) in old_map.iter() {
+                let name = match key {
+                    ron::Value::String(k) => k.clone(),
+                    other => format!("{:?}", other),
+                };
+                let path = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+                match new_map.iter().find(|(k, _)| *k == key).map(|(_, v)| v) {
+                    Some(new_field) => diff_values(&path, old_field, new_field, changes),
+                    None => changes.push(format!("{}: removed", path)),
+                }
+            }
+        },
+        _ if old != new => {
+            let show = |v: &ron::Value| ron::to_string(v).unwrap_or_else(|_| format!("{:?}", v));
+            changes.push(format!("{}: {} -> {}", prefix, show(old), show(new)));
+        },
+        _ => {},
+    }
+}
+
+
+/// A copy of the config with one value changed, addressed as "section.field". Used by the batch runner to sweep
+/// over parameters
+pub fn set_config_value(config: &SimConfig, path: &str, value: f64) -> Result<SimConfig, String> {
+    let mut root = to_value(config).map_err(|e| format!("could not convert the config: {}", e))?;
+    let mut field = &mut root;
+    for name in path.split('.') {
+        let ron::Value::Map(map) = field else { return Err(format!("{}: {} is not a section", path, name)); };
+        let key = ron::Value::String(name.into());
+        field = match map.iter_mut().find(|(k, _)| **k == key) {
+            Some((_, v)) => v,
+            None => return Err(format!("{}: there is no {}", path, name)),
+        };
+    }
+
+    // keep the kind of value the field already has, integers don't deserialize from floats
+    *field = match field {
+        ron::Value::Number(ron::Number::Integer(_)) => ron::Value::Number(ron::Number::Integer(value.round() as i64)),
+        ron::Value::Number(ron::Number::Float(_)) => ron::Value::Number(ron::Number::new(value)),
+        ron::Value::Bool(_) => ron::Value::Bool(value != 0.),
+        _ => return Err(format!("{} is not a number", path)),
+    };
+
+    let config: SimConfig = root.into_rust().map_err(|e| format!("{}: {}", path, e))?;
+    config.validate().map_err(|errors| errors.join(", "))?;
+    Ok(config)
+}
+
+
+/// The value following any of these flags on the command line
+pub fn arg_value(args: &[String], names: &[&str]) -> Result<Option<String>, String> {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if names.contains(&arg.as_str()) {
+            return match iter.next() {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err(format!("{} needs a value", arg)),
+            };
+        }
+    }
+    Ok(None)
+}
+
+
+/// `--config <path>` picks the config file. Without it the default path is used if that file exists,
+/// otherwise the built in defaults
+pub fn config_path_from_args(args: &[String]) -> Result<Option<PathBuf>, String> {
+    if let Some(path) = arg_value(args, &["--config", "-c"])? {
+        return Ok(Some(PathBuf::from(path)));
+    }
+    let default_path = PathBuf::from(DEFAULT_CONFIG_PATH);
+    Ok(if default_path.exists() { Some(default_path) } else { None })
+}
+
+
+
+/// Write the config the run actually used next to its other outputs, with the seed filled in so the run can be repeated
+pub fn dump_effective_config(
+    config: Res<SimConfig>,
+    config_path: Res<ConfigPath>,
+    resume_from: Option<Res<ResumeFrom>>,
+    rng: Res<SimRng>,
+) {
+    let dir = PathBuf::from(&config.world.output_dir);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        warn!("Could not create output directory {:?}: {}", dir, e);
+        return;
+    }
+
+    let source = match (&config_path.0, resume_from) {
+        (Some(path), _) => format!("// loaded from {}\n", path.display()),
+        (None, Some(resume_from)) => format!("// resumed from the snapshot {}\n", resume_from.0.display()),
+        (None, None) => "// built in defaults\n".into(),
+    };
+    let mut config = config.clone();
+    config.world.seed = Some(rng.seed);
+    match ron::ser::to_string_pretty(&config, ron::ser::PrettyConfig::default()) {
+        Ok(text) => {
+            if let Err(e) = fs::write(dir.join(EFFECTIVE_CONFIG_FILE), source + &text) {
+                warn!("Could not write the effective config: {}", e);
+            }
+        },
+        Err(e) => warn!("Could not serialize the effective config: {}", e),
+    }
+}
+
+
+
+/// Re-read the config file when it changes. A file that doesn't parse or validate is reported and ignored, the
+/// simulation keeps running on the last good config. The new config is applied at the start of the next simulation
+/// tick, so it is part of the replay recording
+pub fn hot_reload_config(
+    mut watcher: ResMut<ConfigWatcher>,
+    mut pending: ResMut<PendingInputs>,
+    config_path: Res<ConfigPath>,
+    config: Res<SimConfig>,
+    time: Res<Time<Real>>,
+) {
+    let Some(path) = &config_path.0 else { return; };
+    watcher.timer.tick(time.delta());
+    if !watcher.timer.just_finished() { return; }
+
+    let modified = modified_time(path);
+    if modified.is_none() || modified == watcher.last_modified { return; }
+    watcher.last_modified = modified;
+
+    let new_config = match SimConfig::load(path) {
+        Ok(new_config) => new_config,
+        Err(errors) => {
+            warn!("Not reloading {:?}, the config is invalid:", path);
+            for e in errors {
+                warn!("  {}", e);
+            }
+            return;
+        },
+    };
+
+    let changes = config_changes(&config, &new_config);
+    if changes.is_empty() { return; }
+    info!("Reloaded {:?}:", path);
+    for change in changes.iter() {
+        info!("  {}", change);
+    }
+
+    if new_config.world != config.world {
+        warn!("Changes to the world section only take effect after a restart");
+    }
+    if new_config.obstacles != config.obstacles {
+        warn!("Changes to the obstacles section only take effect after a restart");
+    }
+    pending.push(SimInput::Config(new_config));
+}
+
+
+
+/// Apply a new config to the running simulation. Only the sections that changed are applied, and the world section
+/// is kept as it was, the map and races are already built from it
+pub fn apply_config_change(
+    old: &SimConfig,
+    new: &SimConfig,
+    commands: &mut Commands,
+    ui_state: &mut UiState,
+    stats: Option<&mut Statistics>,
+    species_query: &mut Query<(&mut Species, &mut FoodDesire, &mut WaterDesire)>,
+) {
+    // the sliders show the steering values, so they follow the file
+    if new.movement != old.movement {
+        let movement = &new.movement;
+        ui_state.steering_strength = movement.steering_strength;
+        ui_state.max_velocity = movement.max_velocity;
+        ui_state.max_acceleration = movement.max_acceleration;
+        ui_state.avoid_other_strength = movement.avoid_other_strength;
+        ui_state.avoid_same_strength = movement.avoid_same_strength;
+    }
+
+    if let Some(stats) = stats {
+        if new.statistics != old.statistics {
+            stats.sample_timer.set_duration(std::time::Duration::from_secs_f32(new.statistics.population_sample_sec));
+        }
+    }
+
+    // values the species copied when they were spawned. A species whose value is no longer the old config value
+    // got it from somewhere else, and keeps it. The perception is inherited, so it scales with the config instead
+    let perception_scale = new.movement.perception_radius / old.movement.perception_radius;
+    for (mut sp, mut food_desire, mut water_desire) in species_query.iter_mut() {
+        sp.perception_radius *= perception_scale;
+        if food_desire.in_range_eat == old.hunger.eat_radius {
+            food_desire.in_range_eat = new.hunger.eat_radius;
+        }
+        if water_desire.in_range_drink == old.thirst.drink_range {
+            water_desire.in_range_drink = new.thirst.drink_range;
+        }
+        if water_desire.drink_rate_hz == old.thirst.drink_rate_hz {
+            water_desire.drink_rate_hz = new.thirst.drink_rate_hz;
+        }
+    }
+
+    let mut applied = new.clone();
+    applied.world = old.world.clone();
+    applied.apply_changed(old, commands);
+}
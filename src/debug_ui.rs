@@ -1,7 +1,12 @@
 use bevy_egui::{egui, EguiContexts};
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::weather::*;
+use crate::behavior::MovementConfig;
+use crate::config::WorldConfig;
+use crate::snapshot::*;
 
-#[derive(Default, Resource)]
+#[derive(Default, Resource, Serialize, Deserialize, Clone)]
 pub struct UiState {
     pub is_window_open: bool,
     pub steering_strength: f32,
@@ -17,11 +22,31 @@ pub struct UiState {
 }
 
 
+impl UiState {
+    pub fn new(movement: &MovementConfig) -> Self {
+        Self {
+            steering_strength: movement.steering_strength,
+            max_velocity: movement.max_velocity,
+            max_acceleration: movement.max_acceleration,
+            avoid_other_strength: movement.avoid_other_strength,
+            avoid_same_strength: movement.avoid_same_strength,
+            vector_scaling: 1.0,
+            ..default()
+        }
+    }
+}
+
+
 
 pub fn debug_menu_ui(
     mut commands: Commands,
     mut contexts: EguiContexts,
     mut ui_state: ResMut<UiState>,
+    mut weather_settings: ResMut<WeatherConfig>,
+    weather: Res<Weather>,
+    world: Res<WorldConfig>,
+    mut save_snapshot: EventWriter<SaveSnapshot>,
+    mut load_snapshot: EventWriter<LoadSnapshot>,
 ){
     let ctx = contexts.ctx_mut();
 
@@ -40,6 +65,23 @@ pub fn debug_menu_ui(
             ui.add(egui::Checkbox::new(&mut ui_state.show_physics_vectors, "Show Physics Vectors"));
             ui.add(egui::Slider::new(&mut ui_state.vector_scaling, 1.0..=100.).text("Vector Scale"));
 
+            ui.separator();
+            ui.label(format!("Weather: {:?}", weather.current));
+            ui.add(egui::Checkbox::new(&mut weather_settings.enabled, "Weather Events"));
+            ui.add(egui::Slider::new(&mut weather_settings.drought_per_min, 0.0..=5.0).text("Droughts / min"));
+            ui.add(egui::Slider::new(&mut weather_settings.flood_per_min, 0.0..=5.0).text("Floods / min"));
+            ui.add(egui::Slider::new(&mut weather_settings.storm_per_min, 0.0..=5.0).text("Storms / min"));
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Save (F5)").clicked() {
+                    save_snapshot.send(SaveSnapshot(default_snapshot_path(&world)));
+                }
+                if ui.button("Load (F9)").clicked() {
+                    load_snapshot.send(LoadSnapshot(default_snapshot_path(&world)));
+                }
+            });
+
             ui.allocate_space(egui::Vec2::new(1.0, 10.0));
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
@@ -0,0 +1,353 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use crate::config::WorldConfig;
+use crate::health::*;
+use crate::homebase::*;
+use crate::navigation::{NavGrid, NAV_CELL_SIZE};
+use crate::obstacle::Obstacle;
+use crate::race::*;
+use crate::sim_rng::SimRng;
+use crate::species::*;
+use crate::statistics::StatisticsEvent;
+
+const NODES: u32 = 40;
+const NODE_AMOUNT: f32 = 50.;
+const NODE_CLEARANCE: f32 = 300.;
+const FORAGE_RADIUS: f32 = 1000.;
+const GATHER_RANGE: f32 = 20.;
+const GATHER_STRENGTH: f32 = 2.;
+const CARRY_AMOUNT: f32 = 5.;
+const BUILDER_ENGINEERING: f32 = 1.2;
+const BUILD_INTERVAL_SEC: f32 = 5.;
+const RING_RADIUS: f32 = 150.;
+const RING_SLOTS: u32 = 24;
+const GATE_EVERY: u32 = 6;
+const WALL_STONE: f32 = 10.;
+const TOWER_WOOD: f32 = 10.;
+const TOWER_STONE: f32 = 10.;
+const STORAGE_WOOD: f32 = 20.;
+const WALL_HEALTH: f32 = 50.;
+const TOWER_HEALTH: f32 = 30.;
+const STORAGE_HEALTH: f32 = 30.;
+const TOWER_RANGE: f32 = 150.;
+const TOWER_DAMAGE: f32 = 1.;
+const TOWER_COOLDOWN_SEC: f32 = 1.;
+const STORAGE_CAPACITY: f32 = 100.;
+const STRUCTURE_Z: f32 = -1.;
+const NODE_Z: f32 = -5.;
+
+
+/// Species with enough engineering gather wood and stone from resource nodes and bring it home. A builder at home
+/// turns the materials into walls, towers and storage on a ring around the homebase. Every few slots of the ring
+/// stay open as gates
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineeringConfig {
+    pub nodes: u32, // resource nodes spawned with the world, half wood and half stone
+    pub node_amount: f32,
+    pub node_clearance: f32, // no resource nodes this close to a homebase
+    pub forage_radius: f32, // builders only go for nodes this close to their homebase
+    pub gather_range: f32,
+    pub gather_strength: f32,
+    pub carry_amount: f32,
+    pub builder_engineering: f32, // species with at least this much engineering gather and build
+    pub build_interval_sec: f32, // between two structures of the same homebase
+    pub ring_radius: f32,
+    pub ring_slots: u32,
+    pub gate_every: u32, // every this many slots of the ring is left open
+    pub wall_stone: f32,
+    pub tower_wood: f32,
+    pub tower_stone: f32,
+    pub storage_wood: f32,
+    pub wall_health: f32,
+    pub tower_health: f32,
+    pub storage_health: f32,
+    pub tower_range: f32,
+    pub tower_damage: f32,
+    pub tower_cooldown_sec: f32,
+    pub storage_capacity: f32, // food and water every storage adds to the homebase stockpile
+}
+
+impl Default for EngineeringConfig {
+    fn default() -> Self {
+        Self {
+            nodes: NODES,
+            node_amount: NODE_AMOUNT,
+            node_clearance: NODE_CLEARANCE,
+            forage_radius: FORAGE_RADIUS,
+            gather_range: GATHER_RANGE,
+            gather_strength: GATHER_STRENGTH,
+            carry_amount: CARRY_AMOUNT,
+            builder_engineering: BUILDER_ENGINEERING,
+            build_interval_sec: BUILD_INTERVAL_SEC,
+            ring_radius: RING_RADIUS,
+            ring_slots: RING_SLOTS,
+            gate_every: GATE_EVERY,
+            wall_stone: WALL_STONE,
+            tower_wood: TOWER_WOOD,
+            tower_stone: TOWER_STONE,
+            storage_wood: STORAGE_WOOD,
+            wall_health: WALL_HEALTH,
+            tower_health: TOWER_HEALTH,
+            storage_health: STORAGE_HEALTH,
+            tower_range: TOWER_RANGE,
+            tower_damage: TOWER_DAMAGE,
+            tower_cooldown_sec: TOWER_COOLDOWN_SEC,
+            storage_capacity: STORAGE_CAPACITY,
+        }
+    }
+}
+
+impl EngineeringConfig {
+    pub fn is_builder(&self, sp: &Species) -> bool {
+        sp.engineering >= self.builder_engineering
+    }
+
+    /// Wood and stone a structure costs
+    pub fn cost(&self, kind: StructureKind) -> (f32, f32) {
+        match kind {
+            StructureKind::Wall => (0., self.wall_stone),
+            StructureKind::Tower => (self.tower_wood, self.tower_stone),
+            StructureKind::Storage => (self.storage_wood, 0.),
+        }
+    }
+
+    pub fn health(&self, kind: StructureKind) -> f32 {
+        match kind {
+            StructureKind::Wall => self.wall_health,
+            StructureKind::Tower => self.tower_health,
+            StructureKind::Storage => self.storage_health,
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Material {
+    Wood,
+    Stone,
+}
+
+
+/// Wood or stone to gather, gone once it is used up
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct ResourceNode {
+    pub position: Vec3,
+    pub material: Material,
+    pub amount: f32,
+}
+
+impl ResourceNode {
+    pub fn sprite(&self) -> SpriteBundle {
+        let color = match self.material {
+            Material::Wood => Color::rgb(0.45, 0.3, 0.15),
+            Material::Stone => Color::rgb(0.6, 0.6, 0.6),
+        };
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::splat(NAV_CELL_SIZE / 2.)),
+                ..default()
+            },
+            transform: Transform::from_translation(self.position),
+            ..default()
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    Wall, // only blocks the way
+    Tower, // shoots the enemies in range
+    Storage, // makes room for more food and water in the stockpile
+}
+
+
+/// Built on one slot of the ring around a homebase. Blocks movement like a rock, until enemies tear it down
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub race: SpeciesRace,
+    pub home: Vec3, // position of the homebase it belongs to
+    pub slot: u32,
+    pub position: Vec3,
+    pub health: f32,
+    pub full_health: f32,
+    pub cooldown: f32, // seconds until a tower shoots again
+}
+
+impl Structure {
+    pub fn new(kind: StructureKind, home: &Homebase, slot: u32, config: &EngineeringConfig) -> Self {
+        let angle = 2. * PI * slot as f32 / config.ring_slots as f32;
+        let position = (home.position.xy() + config.ring_radius * Vec2::from_angle(angle)).extend(STRUCTURE_Z);
+        Self {
+            kind,
+            race: home.species_race,
+            home: home.position,
+            slot,
+            position,
+            health: config.health(kind),
+            full_health: config.health(kind),
+            cooldown: 0.,
+        }
+    }
+
+    pub fn belongs_to(&self, home: &Homebase) -> bool {
+        self.race == home.species_race && self.home.xy() == home.position.xy()
+    }
+
+    /// The sprite in the race color, darker the sturdier the structure, and the obstacle it makes
+    pub fn bundle(&self, race_color: Color) -> (SpriteBundle, Obstacle) {
+        let (shade, size) = match self.kind {
+            StructureKind::Wall => (0.4, 1.),
+            StructureKind::Tower => (0.7, 0.8),
+            StructureKind::Storage => (1., 0.9),
+        };
+        let color = Color::rgb(race_color.r() * shade, race_color.g() * shade, race_color.b() * shade);
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(NAV_CELL_SIZE * size)),
+                    ..default()
+                },
+                transform: Transform::from_translation(self.position),
+                ..default()
+            },
+            Obstacle::new(self.position, NAV_CELL_SIZE / 2.),
+        )
+    }
+}
+
+
+
+/// Scatter wood and stone over the map, away from the homebases and off the rocks
+pub fn spawn_resource_nodes(
+    mut commands: Commands,
+    home_query: Query<&Homebase>,
+    obstacles: Query<&Obstacle>,
+    world: Res<WorldConfig>,
+    config: Res<EngineeringConfig>,
+    mut rng: ResMut<SimRng>,
+) {
+    let anywhere = HomebaseRegion { min: (0., 0.), max: (1., 1.) };
+    for i in 0..config.nodes {
+        let position = anywhere.random_position(&mut *rng, &world);
+        let near_home = home_query.iter().any(|home| (home.position.xy() - position).length() < config.node_clearance);
+        let on_rock = obstacles.iter().any(|o| (o.position.xy() - position).abs().max_element() < o.half_size);
+        if near_home || on_rock { continue; }
+
+        let node = ResourceNode {
+            position: position.extend(NODE_Z),
+            material: if i % 2 == 0 { Material::Wood } else { Material::Stone },
+            amount: config.node_amount * rng.gen_range(0.5..=1.5),
+        };
+        commands.spawn((node.sprite(), node));
+    }
+}
+
+
+
+/// A builder at home puts up the next structure once the homebase can pay for it: a tower when the homebase has
+/// been attacked, storage when a stockpile is full, walls otherwise
+pub fn build_structures(
+    mut home_query: Query<&mut Homebase>,
+    structures: Query<&Structure>,
+    species_query: Query<&Species>,
+    mut commands: Commands,
+    mut stats: EventWriter<StatisticsEvent>,
+    config: Res<EngineeringConfig>,
+    homebase: Res<HomebaseConfig>,
+    races: Res<Races>,
+    nav_grid: Res<NavGrid>,
+    time: Res<Time>,
+) {
+    for mut home in home_query.iter_mut() {
+        home.build_cooldown = (home.build_cooldown - time.delta_seconds()).max(0.);
+        if home.build_cooldown > 0. || home.is_destroyed() { continue; }
+        let builder_home = species_query.iter()
+            .any(|sp| home.is_home_of(sp) && config.is_builder(sp) && home.is_near(sp.position, &homebase));
+        if !builder_home { continue; }
+
+        let built: Vec<&Structure> = structures.iter().filter(|s| s.belongs_to(&home)).collect();
+        let (food_capacity, water_capacity) = home.capacity(built.iter().copied(), &homebase, &config);
+        let full = home.food >= food_capacity || home.water >= water_capacity;
+        let kind = if home.health < home.full_health {
+            StructureKind::Tower
+        } else if full {
+            StructureKind::Storage
+        } else {
+            StructureKind::Wall
+        };
+        let (wood, stone) = config.cost(kind);
+        if home.wood < wood || home.stone < stone { continue; }
+
+        // the first free slot that isn't a gate and isn't on a rock
+        let free_slot = (0..config.ring_slots).find(|slot| {
+            if config.gate_every > 0 && slot % config.gate_every == 0 { return false; }
+            if built.iter().any(|s| s.slot == *slot) { return false; }
+            let position = Structure::new(kind, &home, *slot, &config).position;
+            !nav_grid.is_blocked_world(position.xy())
+        });
+        let Some(slot) = free_slot else { continue; };
+
+        home.wood -= wood;
+        home.stone -= stone;
+        home.build_cooldown = config.build_interval_sec;
+        let structure = Structure::new(kind, &home, slot, &config);
+        let name = &races.get(home.species_race).name;
+        commands.spawn((structure.bundle(races.get(home.species_race).color()), structure));
+        stats.send(StatisticsEvent::new("structure_built", &format!("{}:{:?}", name, kind), built.len() as f32 + 1.));
+    }
+}
+
+
+
+/// Towers shoot the nearest enemy in range, one hit per cooldown
+pub fn fire_towers(
+    mut structures: Query<&mut Structure>,
+    mut species_query: Query<(&Species, &mut Health)>,
+    config: Res<EngineeringConfig>,
+    time: Res<Time>,
+) {
+    for mut tower in structures.iter_mut() {
+        if tower.kind != StructureKind::Tower { continue; }
+        tower.cooldown = (tower.cooldown - time.delta_seconds()).max(0.);
+        if tower.cooldown > 0. { continue; }
+
+        let target = species_query.iter_mut()
+            .filter(|(sp, health)| sp.race != tower.race && health.val >= 0.)
+            .map(|(sp, health)| ((sp.position.xy() - tower.position.xy()).length(), health))
+            .filter(|(distance, _)| *distance < config.tower_range)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((_, mut health)) = target {
+            health.damage(config.tower_damage, DeathCause::Structure(tower.race), None);
+            tower.cooldown = config.tower_cooldown_sec;
+        }
+    }
+}
+
+
+
+/// Structures that were torn down, or whose homebase is gone, are removed
+pub fn destroy_structures(
+    structures: Query<(Entity, &Structure)>,
+    home_query: Query<&Homebase>,
+    mut commands: Commands,
+    mut stats: EventWriter<StatisticsEvent>,
+    races: Res<Races>,
+) {
+    for (e, structure) in structures.iter() {
+        let orphaned = !home_query.iter().any(|home| structure.belongs_to(home) && !home.is_destroyed());
+        if structure.health > 0. && !orphaned { continue; }
+
+        commands.entity(e).despawn();
+        if structure.health <= 0. {
+            let label = format!("{}:{:?}", races.get(structure.race).name, structure.kind);
+            stats.send(StatisticsEvent::new("structure_destroyed", &label, structure.slot as f32));
+        }
+    }
+}
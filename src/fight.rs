@@ -1,71 +1,219 @@
 use bevy::prelude::*;
-use crate::food_desire::FoodDesire;
+use crate::engineering::Structure;
+use crate::food_desire::*;
 use crate::health::*;
+use crate::homebase::*;
+use crate::navigation::NAV_CELL_SIZE;
+use crate::reproduce::ReproductionConfig;
+use crate::sim_rng::SimRng;
 use crate::species::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 const ATTACK_THRESHOLD: f32 = 0.9;
+const ATTACK_RANGE: f32 = 20.0;
 const SPECIES_FOOD_EAT_RESTORE: f32 = 25.0;
+const HIT_DAMAGE: f32 = 2.0;
+const HIT_COOLDOWN_SEC: f32 = 1.0;
+const FLEE_HEALTH: f32 = 0.3;
+const FLEE_SEC: f32 = 3.0;
+const FLEE_STRENGTH: f32 = 2.0;
 
 
-#[derive(Component)]
+/// A species attacks the nearest enemy in range when its aggressiveness times its strength relative to the enemy's
+/// is above `attack_threshold`. Strength is fighting_score times attack times the fraction of health left, less
+/// while weakened by an injury
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FightConfig {
+    pub attack_threshold: f32,
+    pub attack_range: f32,
+    pub species_food_eat_restore: f32,
+    pub hit_damage: f32, // damage of a hit with a fighting_score of 1. A defense equal to the attack takes half of it
+    pub hit_cooldown_sec: f32,
+    pub flee_health: f32, // fraction of full health below which a species runs from a stronger attacker
+    pub flee_sec: f32,
+    pub flee_strength: f32,
+}
+
+impl Default for FightConfig {
+    fn default() -> Self {
+        Self {
+            attack_threshold: ATTACK_THRESHOLD,
+            attack_range: ATTACK_RANGE,
+            species_food_eat_restore: SPECIES_FOOD_EAT_RESTORE,
+            hit_damage: HIT_DAMAGE,
+            hit_cooldown_sec: HIT_COOLDOWN_SEC,
+            flee_health: FLEE_HEALTH,
+            flee_sec: FLEE_SEC,
+            flee_strength: FLEE_STRENGTH,
+        }
+    }
+}
+
+
+/// The attack and defense are inherited by the children, the rest is the state of the current fight
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Fight {
-    pub score: f32,
     pub attack_val: f32,
     pub defense_val: f32,
+    pub cooldown: f32, // seconds until the next hit
+    pub flee_from: Option<Vec3>, // where the attacker was when this species started running
+    pub flee_time: f32, // seconds left to run
 }
 
 impl Fight {
-    pub fn new(score: f32, attack_val: f32, defense_val: f32) -> Self {
+    pub fn new(attack_val: f32, defense_val: f32) -> Self {
         Self {
-            score,
             attack_val,
             defense_val,
+            cooldown: 0.,
+            flee_from: None,
+            flee_time: 0.,
         }
     }
     
     pub fn default() -> Self {
-        Self {
-            score: 1.0,
-            attack_val: 1.0,
-            defense_val: 1.0,
-        }
+        Self::new(1.0, 1.0)
+    }
+
+    /// A child fights about like its parent, but isn't in the middle of a fight
+    pub fn inherit(&self, config: &ReproductionConfig, rng: &mut impl Rng) -> Self {
+        Self::new(config.mutate(self.attack_val, rng), config.mutate(self.defense_val, rng))
+    }
+
+    pub fn fleeing(&self) -> bool {
+        self.flee_time > 0.
+    }
+
+    pub fn strength(&self, sp: &Species, health: &Health, config: &HealthConfig) -> f32 {
+        sp.fighting_score * self.attack_val * health.attack_factor(config) * (health.val / health.full).max(0.)
+    }
+
+    /// Damage of one hit on `target`, mitigated by its defense
+    pub fn damage(&self, sp: &Species, target: &Fight, config: &FightConfig) -> f32 {
+        let mitigation = self.attack_val / (self.attack_val + target.defense_val).max(f32::EPSILON);
+        config.hit_damage * sp.fighting_score * self.attack_val * mitigation
     }
 }
 
 
+
+/// Every adult that is ready to hit picks the nearest enemy in range and decides whether to attack it. A species
+/// that is badly hurt by a stronger one runs away for a while and doesn't attack. A hit may leave an injury
 pub fn fight_species(
-    mut query: Query<(&mut Fight, &mut Health, &Transform, &Species, &mut FoodDesire)>,
+    mut query: Query<(Entity, &mut Fight, &mut Health, &Species, &mut FoodDesire)>,
+    config: Res<FightConfig>,
+    health_config: Res<HealthConfig>,
+    aging: Res<AgingConfig>,
+    metabolism: Res<MetabolismConfig>,
+    mut rng: ResMut<SimRng>,
     time: Res<Time>,
 ) {
-    let mut count: i32 = 0;
-    let mut combinations = query.iter_combinations_mut::<2>();
-    while let Some([mut this, other]) = combinations.fetch_next() {
-        let this_fight = this.0;
-        let mut this_health = this.1;
-        let this_tf = this.2;
-        let this_sp = this.3;
-        let mut this_hunger = this.4;
-
-        let other_fight = other.0;
-        let mut other_health = other.1;
-        let other_tf = other.2;
-        let other_sp = other.3;
-
-        if (this_tf.translation - other_tf.translation).length() < 20.0
-            && this_sp.race != other_sp.race {
-            if this_fight.score > ATTACK_THRESHOLD {
-                other_health.val -= this_fight.attack_val * time.delta_seconds();
-                this_health.val -= other_fight.attack_val * time.delta_seconds();
-            // fill hunger if kill other species
-            if other_health.val < 0. {
-                this_hunger.val += SPECIES_FOOD_EAT_RESTORE;
-                
-            }
+    let delta = time.delta_seconds();
+    for (_, mut fight, ..) in query.iter_mut() {
+        fight.cooldown = (fight.cooldown - delta).max(0.);
+        fight.flee_time = (fight.flee_time - delta).max(0.);
+        if !fight.fleeing() { fight.flee_from = None; }
+    }
+
+    // decided before anyone is hit, so the order the species come in doesn't matter
+    let mut hits: Vec<(Entity, Entity, f32)> = Vec::new();
+    for (this_e, this_fight, this_health, this_sp, _) in query.iter() {
+        if this_fight.cooldown > 0. || this_fight.fleeing() || this_health.val < 0. { continue; }
+        if this_sp.stage(&aging) == LifeStage::Juvenile { continue; }
+
+        let mut min_distance = config.attack_range;
+        let mut target = None;
+        for (other_e, other_fight, other_health, other_sp, _) in query.iter() {
+            if other_sp.race == this_sp.race || other_health.val < 0. { continue; }
+            let distance = (other_sp.position.xy() - this_sp.position.xy()).length();
+            if distance < min_distance {
+                min_distance = distance;
+                target = Some((other_e, other_fight, other_health, other_sp));
             }
         }
-        count += 1;
+        let Some((other_e, other_fight, other_health, other_sp)) = target else { continue; };
+
+        let relative_strength = this_fight.strength(this_sp, this_health, &health_config)
+            / other_fight.strength(other_sp, other_health, &health_config).max(f32::EPSILON);
+        if this_sp.aggressiveness * relative_strength > config.attack_threshold {
+            let damage = this_fight.damage(this_sp, other_fight, &config) * this_health.attack_factor(&health_config);
+            hits.push((this_e, other_e, damage));
+        }
+    }
+
+    for (attacker, target, damage) in hits {
+        let Ok([mut this, mut other]) = query.get_many_mut([attacker, target]) else { continue; };
+        this.1.cooldown = config.hit_cooldown_sec;
+        this.4.val -= metabolism.hit_cost * this.1.attack_val;
+        other.2.damage(damage, DeathCause::Combat, Some(attacker));
+        if other.2.val >= 0. && rng.gen_range(0.0..1.0) < health_config.injury_chance {
+            let injury = if rng.gen_bool(0.5) { Injury::Limping } else { Injury::Weakened };
+            other.2.injure(injury, &health_config);
+        }
+
+        // run from the fight when losing it
+        let hurt = other.2.val >= 0. && other.2.val < config.flee_health * other.2.full;
+        if hurt && other.1.strength(other.3, &other.2, &health_config) < this.1.strength(this.3, &this.2, &health_config) {
+            other.1.flee_from = Some(this.3.position);
+            other.1.flee_time = config.flee_sec;
+        }
+    }
+}
+
+
+
+/// Adults that are ready to hit and aggressive enough attack the enemy structures and homebases they are at,
+/// structures first since they stand in the way. Fighting the defenders comes first, a species that hit someone this
+/// tick is still on its cooldown
+pub fn raid_homebases(
+    mut query: Query<(&mut Fight, &Health, &Species, &mut FoodDesire)>,
+    mut home_query: Query<&mut Homebase>,
+    mut structures: Query<&mut Structure>,
+    config: Res<FightConfig>,
+    health_config: Res<HealthConfig>,
+    homebase: Res<HomebaseConfig>,
+    aging: Res<AgingConfig>,
+    metabolism: Res<MetabolismConfig>,
+) {
+    for (mut fight, health, sp, mut food_des) in query.iter_mut() {
+        if fight.cooldown > 0. || fight.fleeing() || health.val < 0. { continue; }
+        if sp.stage(&aging) == LifeStage::Juvenile || sp.aggressiveness <= config.attack_threshold { continue; }
+
+        let damage = config.hit_damage * sp.fighting_score * fight.attack_val * health.attack_factor(&health_config);
+        // the nearest one in reach, the one the raider ran into
+        let reach = config.attack_range + NAV_CELL_SIZE / 2.;
+        let distance = |s: &Structure| (s.position.xy() - sp.position.xy()).length();
+        let structure = structures.iter_mut()
+            .filter(|s| s.race != sp.race && s.health > 0. && distance(s) < reach)
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+        if let Some(mut structure) = structure {
+            structure.health -= damage;
+        } else {
+            let target = home_query.iter_mut()
+                .find(|home| home.species_race != sp.race && home.health > 0. && home.is_near(sp.position, &homebase));
+            let Some(mut home) = target else { continue; };
+            home.health = (home.health - damage).max(0.);
+            home.last_raider = Some(sp.race);
+        }
+        fight.cooldown = config.hit_cooldown_sec;
+        food_des.val -= metabolism.hit_cost * fight.attack_val;
     }
-    // info!("Pairs: {}", count);
+}
 
 
-}
\ No newline at end of file
+
+/// A kill fills the hunger of the species that landed the blow, once
+pub fn feed_killers(
+    mut died: EventReader<Died>,
+    mut query: Query<&mut FoodDesire>,
+    config: Res<FightConfig>,
+) {
+    for died in died.read() {
+        let Some(killer) = died.killer else { continue; };
+        if let Ok(mut hunger) = query.get_mut(killer) {
+            hunger.val += config.species_food_eat_restore;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use crate::homebase::Homebase;
+use crate::water_source::WaterSource;
+use crate::navigation::*;
+
+
+/// For every cell of the navigation grid, the neighboring cell that is one step closer to the target.
+/// Any number of species heading to the same target can look up their direction instead of searching for a path
+pub struct FlowField {
+    pub target: Vec3,
+    pub grid_version: u32,
+    pub distance: Vec<f32>,
+    pub next: Vec<Option<IVec2>>,
+}
+
+impl FlowField {
+    /// Dijkstra outwards from the target cell over every walkable cell
+    pub fn compute(grid: &NavGrid, target: Vec3) -> Self {
+        let n = (grid.width * grid.height) as usize;
+        let mut field = Self {
+            target,
+            grid_version: grid.version,
+            distance: vec![f32::INFINITY; n],
+            next: vec![None; n],
+        };
+        let Some(target_cell) = grid.world_to_cell(target.xy()) else { return field; };
+
+        let mut open = BinaryHeap::new();
+        field.distance[grid.index(target_cell)] = 0.;
+        open.push(FieldNode { cell: target_cell, distance: 0. });
+
+        while let Some(FieldNode { cell, distance }) = open.pop() {
+            if distance > field.distance[grid.index(cell)] { continue; }
+
+            for (next, cost) in grid.walkable_neighbors(cell) {
+                let j = grid.index(next);
+                let tentative = distance + cost;
+                if tentative < field.distance[j] {
+                    field.distance[j] = tentative;
+                    // moving from `next` towards the target goes through `cell`
+                    field.next[j] = Some(cell);
+                    open.push(FieldNode { cell: next, distance: tentative });
+                }
+            }
+        }
+        field
+    }
+
+    /// Direction to steer in from this position. None if the position is off the grid or can't reach the target
+    pub fn direction(&self, grid: &NavGrid, position: Vec3) -> Option<Vec3> {
+        let cell = grid.world_to_cell(position.xy())?;
+        let i = grid.index(cell);
+        if self.distance[i].is_infinite() { return None; }
+
+        let aim = match self.next[i] {
+            Some(next) => grid.cell_to_world(next),
+            None => self.target.xy(), // already in the target cell
+        };
+        Some((aim - position.xy()).extend(0.).normalize_or_zero())
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+struct FieldNode {
+    cell: IVec2,
+    distance: f32,
+}
+
+impl Eq for FieldNode {}
+
+// reversed so the BinaryHeap pops the closest cell first
+impl Ord for FieldNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.partial_cmp(&self.distance).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for FieldNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+
+/// Cached flow fields, one per homebase and per water source, also indexed by the cell their target is in since
+/// every species looks them up several times a tick
+#[derive(Resource, Default)]
+pub struct FlowFields {
+    pub fields: HashMap<Entity, FlowField>,
+    pub by_target: HashMap<IVec2, Entity>,
+}
+
+impl FlowFields {
+    fn target_cell(target: Vec3) -> IVec2 {
+        (target.xy() / NAV_CELL_SIZE).floor().as_ivec2()
+    }
+
+    /// The flow field leading to whatever is at this target position, if there is one
+    pub fn towards(&self, target: Vec3) -> Option<&FlowField> {
+        self.fields.get(self.by_target.get(&Self::target_cell(target))?)
+    }
+
+    pub fn direction(&self, grid: &NavGrid, position: Vec3, target: Vec3) -> Option<Vec3> {
+        self.towards(target)?.direction(grid, position)
+    }
+}
+
+
+
+/// Only recompute a field when its target is new or moved, or the navigation grid changed
+pub fn update_flow_fields(
+    grid: Res<NavGrid>,
+    mut flow_fields: ResMut<FlowFields>,
+    home_query: Query<(Entity, &Homebase)>,
+    water_query: Query<(Entity, &WaterSource)>,
+) {
+    let targets: Vec<(Entity, Vec3)> = home_query.iter().map(|(e, h)| (e, h.position))
+        .chain(water_query.iter().map(|(e, w)| (e, w.position)))
+        .collect();
+
+    // drop the fields of targets that no longer exist
+    flow_fields.fields.retain(|e, _| targets.iter().any(|(t, _)| t == e));
+
+    for (e, target) in targets {
+        let up_to_date = match flow_fields.fields.get(&e) {
+            Some(field) => field.grid_version == grid.version && field.target == target,
+            None => false,
+        };
+        if !up_to_date {
+            flow_fields.fields.insert(e, FlowField::compute(&grid, target));
+        }
+    }
+
+    let by_target = flow_fields.fields.iter().map(|(e, f)| (FlowFields::target_cell(f.target), *e)).collect();
+    flow_fields.by_target = by_target;
+}
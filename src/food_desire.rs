@@ -1,21 +1,93 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::behavior::MovementConfig;
+use crate::fight::Fight;
+use crate::species::*;
 
-pub const MAX_HUNGER: f32 = 5.;
-const HUNGER_RATE_HZ: f32 = 1.0;
+const MAX_HUNGER: f32 = 5.;
+const HUNGER_RATE_HZ: f32 = 0.5;
 const EAT_RADIUS: f32 = 20.;
+const SPEED_COST: f32 = 0.3;
+const ACCELERATION_COST: f32 = 0.1;
+const PERCEPTION_COST: f32 = 0.1;
+const PERCEPTION_UNIT: f32 = 100.;
+const SIZE_COST: f32 = 0.1;
+const HIT_COST: f32 = 0.2;
+const REPRODUCTION_COST: f32 = 1.0;
+const GATHER_COST: f32 = 0.5;
 
-#[derive(Component)]
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HungerConfig {
+    pub max_hunger: f32,
+    pub hunger_rate_hz: f32,
+    pub eat_radius: f32,
+}
+
+impl Default for HungerConfig {
+    fn default() -> Self {
+        Self {
+            max_hunger: MAX_HUNGER,
+            hunger_rate_hz: HUNGER_RATE_HZ,
+            eat_radius: EAT_RADIUS,
+        }
+    }
+}
+
+
+/// What being alive costs on top of `hunger_rate_hz`, all taken from the food. Moving fast, seeing far, a big and
+/// well armed body, fighting, gathering and having children each cost something, so no trait comes for free
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetabolismConfig {
+    pub speed_cost: f32, // hunger per second at max velocity, grows with the square of the speed
+    pub acceleration_cost: f32, // hunger per unit of velocity gained or lost
+    pub perception_cost: f32, // hunger per second for every 100 of perception radius
+    pub size_cost: f32, // hunger per second for a grown body with an attack and defense of 1
+    pub hit_cost: f32, // hunger per hit, times the attack
+    pub reproduction_cost: f32, // hunger the parent loses with every child
+    pub gather_cost: f32, // hunger for every load of wood or stone picked up
+}
+
+impl Default for MetabolismConfig {
+    fn default() -> Self {
+        Self {
+            speed_cost: SPEED_COST,
+            acceleration_cost: ACCELERATION_COST,
+            perception_cost: PERCEPTION_COST,
+            size_cost: SIZE_COST,
+            hit_cost: HIT_COST,
+            reproduction_cost: REPRODUCTION_COST,
+            gather_cost: GATHER_COST,
+        }
+    }
+}
+
+impl MetabolismConfig {
+    /// Hunger per second for keeping a body moving, seeing and in shape
+    pub fn upkeep(&self, sp: &Species, fight: Option<&Fight>, movement: &MovementConfig, aging: &AgingConfig) -> f32 {
+        let speed = sp.velocity.length() / movement.max_velocity;
+        let build = fight.map_or(1., |fight| (fight.attack_val + fight.defense_val) / 2.);
+        self.speed_cost * speed * speed
+            + self.perception_cost * sp.perception_radius / PERCEPTION_UNIT
+            + self.size_cost * sp.size(aging) * build
+    }
+}
+
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct FoodDesire {
     pub val: f32,
     pub in_range_eat: f32,
 }
 
 
-impl Default for FoodDesire {
-    fn default() -> Self {
+impl FoodDesire {
+    pub fn new(config: &HungerConfig) -> Self {
         Self {
-            val: MAX_HUNGER,
-            in_range_eat: EAT_RADIUS,
+            val: config.max_hunger,
+            in_range_eat: config.eat_radius,
         }
     }
 }
@@ -23,16 +95,18 @@ impl Default for FoodDesire {
 
 
 pub fn update_hunger(
-    mut query: Query<(Entity, &mut FoodDesire)>,
+    mut query: Query<(&mut FoodDesire, &Species, Option<&Fight>)>,
+    config: Res<HungerConfig>,
+    metabolism: Res<MetabolismConfig>,
+    movement: Res<MovementConfig>,
+    aging: Res<AgingConfig>,
     time: Res<Time>,
 ){
-    for (e, mut food) in query.iter_mut() {
+    for (mut food, sp, fight) in query.iter_mut() {
         
         // constantly remove hunger each update. Different from water desire which only removes if
         // it is not replenishing, because eating food should be instantaneous
-        food.val -= HUNGER_RATE_HZ * time.delta_seconds();
+        let rate = config.hunger_rate_hz + metabolism.upkeep(sp, fight, &movement, &aging);
+        food.val -= rate * time.delta_seconds();
     }
 }
-
-
-
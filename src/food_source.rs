@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 use rand::{self, Rng};
 use noise::{NoiseFn, Perlin, Fbm};
-use crate::{MAP_HEIGHT, MAP_WIDTH};
+use serde::{Deserialize, Serialize};
+use crate::config::WorldConfig;
+use crate::weather::*;
+use crate::sim_rng::SimRng;
 use ndarray::Array;
 
 const PERLIN_X_POINTS: usize = 50;
@@ -12,14 +15,52 @@ const SPAWN_SPREAD: f32 = 30.0;
 const PERLIN_ELEVATION_THRESHOLD: f64 = 0.9999; // between 0.0 and 1.0
 const N_FOOD_MAX: i32 = 20;
 const REPLENISH_CHANCE: f32 = 0.001;
+const WITHER_RATE_HZ: f32 = 0.5;
+const CORPSE_VALUE: f32 = 25.;
+const CORPSE_DECAY_RATE_HZ: f32 = 1.;
+const CORPSE_COLOR: Color = Color::rgb(0.5, 0.1, 0.1);
+pub const FOOD_TEXTURE: &str = "textures/food/food_1.png";
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodConfig {
+    pub replenish_min: f32,
+    pub replenish_max: f32,
+    pub spawn_spread: f32,
+    pub elevation_threshold: f64,
+    pub n_food_max: i32,
+    pub replenish_chance: f32,
+    pub wither_rate_hz: f32,
+    pub corpse_value: f32, // food left behind by a dead species
+    pub corpse_decay_rate_hz: f32,
+}
 
-#[derive(Component)]
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self {
+            replenish_min: FOOD_REPLENISH_MIN,
+            replenish_max: FOOD_REPLENISH_MAX,
+            spawn_spread: SPAWN_SPREAD,
+            elevation_threshold: PERLIN_ELEVATION_THRESHOLD,
+            n_food_max: N_FOOD_MAX,
+            replenish_chance: REPLENISH_CHANCE,
+            wither_rate_hz: WITHER_RATE_HZ,
+            corpse_value: CORPSE_VALUE,
+            corpse_decay_rate_hz: CORPSE_DECAY_RATE_HZ,
+        }
+    }
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct FoodSource {
     pub value: f32,
     pub position: Vec3,
+    #[serde(default)]
+    pub decay_rate_hz: f32, // only corpses rot
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
 pub struct FoodLocations {
     pub position: Vec<Vec2>,
 }
@@ -29,11 +70,35 @@ pub struct FoodLocations {
 
 // random value for hunger replenishment
 impl FoodSource {
-    fn new(position: Vec3) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new(position: Vec3, config: &FoodConfig, rng: &mut impl Rng) -> Self {
+        Self {
+            value: rng.gen_range(config.replenish_min..=config.replenish_max),
+            position,
+            decay_rate_hz: 0.,
+        }
+    }
+
+    pub fn corpse(position: Vec3, config: &FoodConfig) -> Self {
         Self {
-            value: rng.gen_range(FOOD_REPLENISH_MIN..=FOOD_REPLENISH_MAX),
+            value: config.corpse_value,
             position,
+            decay_rate_hz: config.corpse_decay_rate_hz,
+        }
+    }
+
+    pub fn is_corpse(&self) -> bool {
+        self.decay_rate_hz > 0.
+    }
+
+    pub fn sprite(&self, texture: Handle<Image>) -> SpriteBundle {
+        SpriteBundle {
+            transform: Transform::from_translation(self.position),
+            texture,
+            sprite: Sprite {
+                color: if self.is_corpse() { CORPSE_COLOR } else { Color::WHITE },
+                ..default()
+            },
+            ..default()
         }
     }
 }
@@ -43,16 +108,17 @@ pub fn spawn_food_sources(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut food_locations: ResMut<FoodLocations>,
+    world: Res<WorldConfig>,
+    config: Res<FoodConfig>,
+    mut rng: ResMut<SimRng>,
 ){
 
-    let mut rng = rand::thread_rng();
-
     // generate a 2D perlin noise surface for the entire map. Size in pixels
     let perlin = Perlin::new(2);
     // let fractal_brownian_motion: Fbm<Perlin> = Fbm::default();
     let mut noise_values: Vec<f64> = Vec::new();
-    let x_coords = Array::linspace(-MAP_WIDTH/2., MAP_WIDTH/2., PERLIN_X_POINTS);
-    let y_coords = Array::linspace(-MAP_HEIGHT/2., MAP_HEIGHT/2., PERLIN_Y_POINTS);
+    let x_coords = Array::linspace(-world.map_width/2., world.map_width/2., PERLIN_X_POINTS);
+    let y_coords = Array::linspace(-world.map_height/2., world.map_height/2., PERLIN_Y_POINTS);
     for x in x_coords.iter() {
         for y in y_coords.iter() {
             let value = perlin.get([*x as f64, *y as f64]);
@@ -65,22 +131,22 @@ pub fn spawn_food_sources(
 
 
     // loop through grid of centers and spawn food
-    let food_handle: Handle<Image> = asset_server.load("textures/food/food_1.png");
+    let food_handle: Handle<Image> = asset_server.load(FOOD_TEXTURE);
     for x in 0..PERLIN_X_POINTS {
         for y in 0..PERLIN_Y_POINTS {
             // if the elevation of the perlin noise surface is above some threshold, spawn a cluster of food there
             let index = PERLIN_X_POINTS * x + y;
-            if noise_values[index] > PERLIN_ELEVATION_THRESHOLD {
+            if noise_values[index] > config.elevation_threshold {
 
                 let x_world = x_coords[x];
                 let y_world = y_coords[y];
 
                 food_locations.position.push(Vec2::new(x_world, y_world));
                 
-                let n_food = rng.gen_range(1..N_FOOD_MAX);
+                let n_food = rng.gen_range(1..config.n_food_max);
                 for _ in 0..n_food {
-                    let x_offset = rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD);
-                    let y_offset = rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD);
+                    let x_offset = rng.gen_range(-config.spawn_spread..config.spawn_spread);
+                    let y_offset = rng.gen_range(-config.spawn_spread..config.spawn_spread);
                     
                     let x_coord = x_world + x_offset;
                     let y_coord = y_world + y_offset;
@@ -90,7 +156,7 @@ pub fn spawn_food_sources(
                             transform: Transform::from_xyz(x_coord, y_coord, 0.),
                             texture: food_handle.clone(), 
                             ..default()},
-                        FoodSource::new(Vec3::new(x_coord, y_coord, 0.)),
+                        FoodSource::new(Vec3::new(x_coord, y_coord, 0.), &config, &mut *rng),
                     ));
                 }
             }
@@ -104,18 +170,22 @@ pub fn spawn_food_replenish(
     mut commands: Commands,
     food_locations: Res<FoodLocations>,
     asset_server: Res<AssetServer>,
+    weather: Res<Weather>,
+    config: Res<FoodConfig>,
+    mut rng: ResMut<SimRng>,
 ){
-    let mut rng = rand::thread_rng();
+    // nothing grows back during a drought
+    if weather.current == WeatherKind::Drought { return; }
 
-    let food_handle: Handle<Image> = asset_server.load("textures/food/food_1.png");
+    let food_handle: Handle<Image> = asset_server.load(FOOD_TEXTURE);
     for pos in food_locations.position.iter() {
 
         let chance = rng.gen_range(0.0..1.0);
-        if chance < REPLENISH_CHANCE {
+        if chance < config.replenish_chance {
             // info!("Spawn new food");
 
-            let x_spawn = pos.x + rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD);
-            let y_spawn = pos.y + rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD);
+            let x_spawn = pos.x + rng.gen_range(-config.spawn_spread..config.spawn_spread);
+            let y_spawn = pos.y + rng.gen_range(-config.spawn_spread..config.spawn_spread);
 
 
             commands.spawn((
@@ -123,8 +193,47 @@ pub fn spawn_food_replenish(
                     transform: Transform::from_xyz(x_spawn, y_spawn, 0.),
                     texture: food_handle.clone(),
                     ..default()},
-                FoodSource::new(Vec3::new(x_spawn, y_spawn, 0.)),
+                FoodSource::new(Vec3::new(x_spawn, y_spawn, 0.), &config, &mut *rng),
             ));
         }
     }
-}
\ No newline at end of file
+}
+
+
+
+// during a drought the food loses its value over time, and disappears once there is nothing left
+pub fn wither_food(
+    mut query: Query<(Entity, &mut FoodSource)>,
+    weather: Res<Weather>,
+    config: Res<FoodConfig>,
+    time: Res<Time>,
+    mut commands: Commands,
+){
+    if weather.current != WeatherKind::Drought { return; }
+
+    for (e, mut food_source) in query.iter_mut() {
+        food_source.value -= config.wither_rate_hz * time.delta_seconds();
+        if food_source.value <= 0. {
+            commands.entity(e).despawn();
+        }
+    }
+}
+
+
+
+// corpses lose their value over time whatever the weather, and disappear once there is nothing left
+pub fn decay_food(
+    mut query: Query<(Entity, &mut FoodSource)>,
+    time: Res<Time>,
+    mut commands: Commands,
+){
+    for (e, mut food_source) in query.iter_mut() {
+        // withered away already
+        if !food_source.is_corpse() || food_source.value <= 0. { continue; }
+
+        food_source.value -= food_source.decay_rate_hz * time.delta_seconds();
+        if food_source.value <= 0. {
+            commands.entity(e).despawn();
+        }
+    }
+}
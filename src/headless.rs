@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::render::settings::WgpuSettings;
+use bevy::render::RenderPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use std::time::Duration;
+use crate::clock::SIM_TICK_HZ;
+
+/// Set with `--headless`: no window and no UI, and every frame is exactly one simulation tick, so the simulation
+/// runs as fast as it can instead of in real time. The run ends on the conditions in `termination`
+#[derive(Resource, Clone, Debug)]
+pub struct Headless;
+
+
+/// Bevy's plugins for running without a window: nothing is rendered and the app updates in a loop as fast as it can.
+/// Use it instead of `DefaultPlugins`
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings { backends: None, ..default() }.into(),
+                })
+                .disable::<WinitPlugin>()
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1. / SIM_TICK_HZ)))
+        .insert_resource(Headless);
+    }
+}
@@ -1,36 +1,153 @@
 use bevy::prelude::*;
-use crate::WaterDesire;
+use crate::water_desire::WaterDesire;
 use crate::fight::*;
 use crate::food_desire::FoodDesire;
+use crate::food_source::*;
+use crate::race::SpeciesRace;
+use crate::species::*;
+use serde::{Deserialize, Serialize};
 
 
-pub const MAX_HEALTH: f32 = 10.;
+const MAX_HEALTH: f32 = 10.;
 const LOW_WATER_DAMAGE_RATE: f32 = 0.1;
 const LOW_HUNGER_DAMAGE_RATE: f32 = 0.1;
 const BLOOD_FADE_TIME: f32 = 10.;
+const REGEN_RATE_HZ: f32 = 0.1;
+const HOME_RADIUS: f32 = 100.;
+const HOME_REGEN_FACTOR: f32 = 3.;
+const INJURY_CHANCE: f32 = 0.2;
+const INJURY_SEC: f32 = 30.;
+const INJURY_SPEED: f32 = 0.5;
+const INJURY_ATTACK: f32 = 0.5;
+const HEALTH_BAR_ZOOM: f32 = 1.;
+const HEALTH_BAR_WIDTH: f32 = 24.;
+const HEALTH_BAR_OFFSET: f32 = 10.; // above the center of the sprite, times its scale
 
 
-#[derive(Component)]
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub max_health: f32,
+    pub low_water_damage_rate: f32,
+    pub low_hunger_damage_rate: f32,
+    pub blood_fade_time: f32,
+    pub regen_rate_hz: f32, // health per second while fed and watered. Elders don't heal
+    pub home_radius: f32,
+    pub home_regen_factor: f32, // health and injuries heal this much faster within home_radius of the homebase
+    pub injury_chance: f32, // of every hit in a fight
+    pub injury_sec: f32,
+    pub injury_speed: f32, // fraction of the max velocity left with a limp
+    pub injury_attack: f32, // fraction of the attack left when weakened
+    pub health_bar_zoom: f32, // health bars are drawn when the camera is zoomed in closer than this scale
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_health: MAX_HEALTH,
+            low_water_damage_rate: LOW_WATER_DAMAGE_RATE,
+            low_hunger_damage_rate: LOW_HUNGER_DAMAGE_RATE,
+            blood_fade_time: BLOOD_FADE_TIME,
+            regen_rate_hz: REGEN_RATE_HZ,
+            home_radius: HOME_RADIUS,
+            home_regen_factor: HOME_REGEN_FACTOR,
+            injury_chance: INJURY_CHANCE,
+            injury_sec: INJURY_SEC,
+            injury_speed: INJURY_SPEED,
+            injury_attack: INJURY_ATTACK,
+            health_bar_zoom: HEALTH_BAR_ZOOM,
+        }
+    }
+}
+
+
+/// What took the last of a species' health
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeathCause {
+    Combat,
+    Starvation,
+    Dehydration,
+    OldAge,
+    Environment, // the weather or the world itself
+    Structure(SpeciesRace), // shot by a tower of this race
+}
+
+
+/// A hit in a fight can leave a species limping or weakened for a while
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Injury {
+    Limping, // slower
+    Weakened, // hits softer and is weaker in a fight
+}
+
+
+/// Sent by `kill_zero_health` for every species that dies, the tick it dies. The entity is gone by the time the
+/// event is read, so everything worth knowing about it is in here
+#[derive(Event, Clone, Debug)]
+pub struct Died {
+    pub entity: Entity,
+    pub race: SpeciesRace,
+    pub position: Vec3,
+    pub age: f32, // simulated seconds
+    pub generation: u32,
+    pub lineage: u32,
+    pub cause: Option<DeathCause>, // None when the health was set from outside the simulation
+    pub killer: Option<Entity>, // whoever landed the blow, when killed in a fight
+}
+
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Health {
     pub val: f32,
     pub full: f32,
+    #[serde(default)]
+    pub last_damage: Option<DeathCause>,
+    #[serde(skip)]
+    pub last_attacker: Option<Entity>, // entities don't keep their id through a snapshot
+    #[serde(default)]
+    pub injury: Option<Injury>,
+    #[serde(default)]
+    pub injury_time: f32, // seconds until the injury has healed
 }
 
 
 impl Health {
-    pub fn default() -> Self {
-        Self {
-            val: MAX_HEALTH,
-            full: MAX_HEALTH,
-        }
+    pub fn full(config: &HealthConfig) -> Self {
+        Self::new(config.max_health, config.max_health)
     }
 
     pub fn new(val: f32, full: f32) -> Self {
         Self {
             val,
             full,
+            last_damage: None,
+            last_attacker: None,
+            injury: None,
+            injury_time: 0.,
         }
     }
+
+    /// Damage a species that is still alive, remembering what did it
+    pub fn damage(&mut self, amount: f32, cause: DeathCause, attacker: Option<Entity>) {
+        if self.val < 0. { return; }
+        self.val -= amount;
+        self.last_damage = Some(cause);
+        self.last_attacker = attacker;
+    }
+
+    /// A new injury replaces the old one
+    pub fn injure(&mut self, injury: Injury, config: &HealthConfig) {
+        self.injury = Some(injury);
+        self.injury_time = config.injury_sec;
+    }
+
+    pub fn speed_factor(&self, config: &HealthConfig) -> f32 {
+        if self.injury == Some(Injury::Limping) { config.injury_speed } else { 1. }
+    }
+
+    pub fn attack_factor(&self, config: &HealthConfig) -> f32 {
+        if self.injury == Some(Injury::Weakened) { config.injury_attack } else { 1. }
+    }
 }
 
 
@@ -41,9 +158,9 @@ pub struct Blood {
 
 
 impl Blood {
-    pub fn default() -> Self {
+    pub fn new(config: &HealthConfig) -> Self {
         Self {
-            timer: Timer::from_seconds(BLOOD_FADE_TIME, TimerMode::Once),
+            timer: Timer::from_seconds(config.blood_fade_time, TimerMode::Once),
         }
     }
 }
@@ -72,28 +189,57 @@ pub fn fade_out_blood(
 
 
 pub fn kill_zero_health(
-    mut query: Query<(Entity, &Health, &Transform)>,
+    query: Query<(Entity, &Health, &Species)>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    mut died: EventWriter<Died>,
 ){
-    
-    let mut blood_handle: Handle<Image> = asset_server.load("textures/blood_splat_1.png");
-    for (e, h, tf) in query.iter_mut() {
+    for (e, h, sp) in query.iter() {
         if h.val < 0. {
             commands.entity(e).despawn();
-            commands.spawn((
-                SpriteBundle{
-                    texture: blood_handle.clone(),
-                    transform: tf.clone(),
-                    ..default()
-                }, 
-                Blood::default()));
+            died.send(Died {
+                entity: e,
+                race: sp.race,
+                position: sp.position,
+                age: sp.age,
+                generation: sp.generation,
+                lineage: sp.lineage,
+                cause: h.last_damage,
+                killer: if h.last_damage == Some(DeathCause::Combat) { h.last_attacker } else { None },
+            });
         }
     }
 }
 
 
 
+/// Dead species leave blood and a corpse behind, which scavengers can eat until it has rotted away
+pub fn spawn_remains(
+    mut died: EventReader<Died>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<HealthConfig>,
+    food_config: Res<FoodConfig>,
+){
+    if died.is_empty() { return; }
+
+    let blood_handle: Handle<Image> = asset_server.load("textures/blood_splat_1.png");
+    let food_handle: Handle<Image> = asset_server.load(FOOD_TEXTURE);
+    for died in died.read() {
+        commands.spawn((
+            SpriteBundle{
+                texture: blood_handle.clone(),
+                transform: Transform::from_translation(died.position).with_scale(Vec3::splat(SPECIES_TEXTURE_SCALE)),
+                ..default()
+            }, 
+            Blood::new(&config)));
+
+        let corpse = FoodSource::corpse(died.position.truncate().extend(0.), &food_config);
+        commands.spawn((corpse.sprite(food_handle.clone()), corpse));
+    }
+}
+
+
+
 
 
 // TODO make this a function for all cases where it should lose health? Food, water, fighting? Or
@@ -101,15 +247,67 @@ pub fn kill_zero_health(
 // Different damage rates for each damage type?
 pub fn damage_low_stats(
     mut query: Query<(&mut Health, &mut WaterDesire, &mut FoodDesire, &mut Fight)>,
+    config: Res<HealthConfig>,
     time: Res<Time>,
 ) {
     for (mut health, water_desire, food_desire, mut fight) in query.iter_mut() {
         
         if water_desire.val < 0.0 {
-            health.val -= LOW_WATER_DAMAGE_RATE * time.delta_seconds();
+            health.damage(config.low_water_damage_rate * time.delta_seconds(), DeathCause::Dehydration, None);
         }
         if food_desire.val < 0.0 {
-            health.val -= LOW_HUNGER_DAMAGE_RATE * time.delta_seconds();
+            health.damage(config.low_hunger_damage_rate * time.delta_seconds(), DeathCause::Starvation, None);
+        }
+    }
+}
+
+
+
+/// Species that are fed and watered heal, faster when resting at their homebase. Injuries heal with time anywhere,
+/// also faster at home
+pub fn regenerate_health(
+    mut query: Query<(&mut Health, &Species, &FoodDesire, &WaterDesire)>,
+    config: Res<HealthConfig>,
+    aging: Res<AgingConfig>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (mut health, sp, food_desire, water_desire) in query.iter_mut() {
+        let at_home = sp.homebase.is_some_and(|home| (sp.position.xy() - home.xy()).length() < config.home_radius);
+        let factor = if at_home { config.home_regen_factor } else { 1. };
+
+        if health.injury.is_some() {
+            health.injury_time -= factor * delta;
+            if health.injury_time <= 0. {
+                health.injury = None;
+                health.injury_time = 0.;
+            }
         }
+
+        if health.val < 0. || food_desire.val <= 0. || water_desire.val <= 0. { continue; }
+        if sp.stage(&aging) == LifeStage::Elder { continue; }
+        health.val = (health.val + config.regen_rate_hz * factor * delta).min(health.full);
+    }
+}
+
+
+
+/// A bar above every species, green when healthy and orange while injured. Only drawn when zoomed in, at the
+/// whole map they would just be noise
+pub fn draw_health_bars(
+    query: Query<(&Health, &Transform), With<Species>>,
+    camera: Query<&OrthographicProjection, With<Camera>>,
+    config: Res<HealthConfig>,
+    mut gizmos: Gizmos,
+) {
+    let Ok(projection) = camera.get_single() else { return; };
+    if projection.scale > config.health_bar_zoom { return; }
+
+    for (health, tf) in query.iter() {
+        let fraction = (health.val / health.full).clamp(0., 1.);
+        let left = tf.translation.xy() + Vec2::new(-HEALTH_BAR_WIDTH / 2., HEALTH_BAR_OFFSET * tf.scale.y);
+        let color = if health.injury.is_some() { Color::ORANGE } else { Color::GREEN };
+        gizmos.line_2d(left, left + Vec2::X * HEALTH_BAR_WIDTH, Color::DARK_GRAY);
+        gizmos.line_2d(left, left + Vec2::X * HEALTH_BAR_WIDTH * fraction, color);
     }
 }
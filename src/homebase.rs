@@ -1,26 +1,145 @@
 use bevy::prelude::*;
-use rand::Rng;
-use crate::{MAP_WIDTH, MAP_HEIGHT};
-use crate::SpeciesRace;
+use serde::{Deserialize, Serialize};
+use crate::race::*;
+use crate::config::WorldConfig;
+use crate::engineering::{Material, Structure, StructureKind, EngineeringConfig};
+use crate::food_desire::*;
+use crate::food_source::FoodLocations;
+use crate::navigation::NavGrid;
+use crate::sim_rng::SimRng;
+use crate::species::*;
+use crate::statistics::StatisticsEvent;
+use crate::water_desire::WaterDesire;
+use crate::water_source::WaterSource;
 
+pub const HOMEBASE_SPRITE_SCALE: f32 = 2.;
+const RADIUS: f32 = 100.;
+const FOOD_CAPACITY: f32 = 200.;
+const WATER_CAPACITY: f32 = 100.;
+const MAX_HEALTH: f32 = 100.;
+const CARRY_WATER: f32 = 5.;
+const RELOCATE_POPULATION: u32 = 3;
+const SITE_CLEARANCE: f32 = 600.;
+const SITE_CANDIDATES: u32 = 20;
+const CROWDING_POPULATION: u32 = 40;
+const COLONY_SIZE: u32 = 10;
+const SPLIT_COOLDOWN_SEC: f32 = 60.;
+const HOMEBASE_Z: f32 = -10.;
 
-#[derive(Component)]
+
+/// The homebase is the colony's store. Species drop off the food they ate beyond a full stomach and the water they
+/// carried back, and take from the stockpile when they are hungry or thirsty at home. When enemies destroy it, the
+/// raiders take what is left of the stockpile and the survivors move to a new site or become nomads. A crowded
+/// homebase sends colonists off to found a new one
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomebaseConfig {
+    pub radius: f32, // species this close to the center are at home, and enemies this close can attack it
+    pub food_capacity: f32,
+    pub water_capacity: f32,
+    pub max_health: f32,
+    pub carry_water: f32, // water a species takes along for the colony after drinking
+    pub relocate_population: u32, // survivors needed to found a new homebase, fewer become nomads
+    pub site_clearance: f32, // a new homebase keeps at least this distance from every other one
+    pub site_candidates: u32, // random sites tried for a new homebase
+    pub crowding_population: u32, // a homebase with more species than this sends colonists off
+    pub colony_size: u32, // adults that leave to found a colony
+    pub split_cooldown_sec: f32, // before the same homebase sends off the next colony
+}
+
+impl Default for HomebaseConfig {
+    fn default() -> Self {
+        Self {
+            radius: RADIUS,
+            food_capacity: FOOD_CAPACITY,
+            water_capacity: WATER_CAPACITY,
+            max_health: MAX_HEALTH,
+            carry_water: CARRY_WATER,
+            relocate_population: RELOCATE_POPULATION,
+            site_clearance: SITE_CLEARANCE,
+            site_candidates: SITE_CANDIDATES,
+            crowding_population: CROWDING_POPULATION,
+            colony_size: COLONY_SIZE,
+            split_cooldown_sec: SPLIT_COOLDOWN_SEC,
+        }
+    }
+}
+
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Homebase {
     pub position: Vec3,
     pub species_race: SpeciesRace,
-
-
+    #[serde(default)]
+    pub food: f32,
+    #[serde(default)]
+    pub water: f32,
+    #[serde(default = "default_health")]
+    pub health: f32,
+    #[serde(default = "default_health")]
+    pub full_health: f32,
+    #[serde(default)]
+    pub population: u32, // species living here, counted every tick
+    #[serde(default)]
+    pub last_raider: Option<SpeciesRace>, // the race that destroys it when the health runs out
+    #[serde(default)]
+    pub wood: f32,
+    #[serde(default)]
+    pub stone: f32,
+    #[serde(default)]
+    pub build_cooldown: f32, // seconds until the next structure can be built
+    #[serde(default)]
+    pub split_cooldown: f32, // seconds until the next colony can leave
 }
 
 
+fn default_health() -> f32 { MAX_HEALTH }
+
 
 impl Homebase {
-    pub fn new(position: Vec3, species_race: SpeciesRace) -> Self {
+    pub fn new(position: Vec3, species_race: SpeciesRace, config: &HomebaseConfig) -> Self {
         Self {
             position,
             species_race,
+            food: 0.,
+            water: 0.,
+            health: config.max_health,
+            full_health: config.max_health,
+            population: 0,
+            last_raider: None,
+            wood: 0.,
+            stone: 0.,
+            build_cooldown: 0.,
+            split_cooldown: 0.,
         }
     }
+
+    pub fn sprite(&self, race_assets: &RaceAssets) -> SpriteBundle {
+        race_assets.homebase_sprite(self.species_race, Transform {
+            translation: self.position,
+            rotation: Quat::default(),
+            scale: Vec3::splat(HOMEBASE_SPRITE_SCALE),
+        })
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.health <= 0.
+    }
+
+    /// Whether this is the homebase of the species
+    pub fn is_home_of(&self, sp: &Species) -> bool {
+        self.species_race == sp.race && sp.homebase.is_some_and(|home| home.xy() == self.position.xy())
+    }
+
+    pub fn is_near(&self, position: Vec3, config: &HomebaseConfig) -> bool {
+        (position.xy() - self.position.xy()).length() < config.radius
+    }
+
+    /// The most food and water the stockpile holds. Every storage built at this homebase makes room for more
+    pub fn capacity<'a>(&self, structures: impl IntoIterator<Item = &'a Structure>, config: &HomebaseConfig, engineering: &EngineeringConfig) -> (f32, f32) {
+        let storages = structures.into_iter().filter(|s| s.kind == StructureKind::Storage && s.belongs_to(self)).count() as f32;
+        (config.food_capacity + storages * engineering.storage_capacity, config.water_capacity + storages * engineering.storage_capacity)
+    }
 }
 
 
@@ -28,58 +147,267 @@ impl Homebase {
 
 pub fn create_homebases(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    races: Res<Races>,
+    race_assets: Res<RaceAssets>,
+    world: Res<WorldConfig>,
+    config: Res<HomebaseConfig>,
+    mut rng: ResMut<SimRng>,
 ){
-    let mut rng = rand::thread_rng();
-    for i in 1..5 {
-        // spawn homebases in 4 quadrants in the corners
-        const OFFSET_X: f32 = MAP_WIDTH * 0.3;
-        const OFFSET_Y: f32 = MAP_HEIGHT * 0.3;
-        let mut min_x: f32 = 0.;
-        let mut max_x: f32 = 0.;
-        let mut min_y: f32 = 0.;
-        let mut max_y: f32 = 0.;
-        match i {
-            1 => { // upper left
-                min_x = -MAP_WIDTH/2.; max_x = -MAP_WIDTH/2. + OFFSET_X; min_y = MAP_HEIGHT/2. - OFFSET_Y ; max_y = MAP_HEIGHT/2.;},
-            2 => { // upper right
-                min_x = MAP_WIDTH/2. - OFFSET_X; max_x = MAP_WIDTH/2.; min_y = MAP_HEIGHT/2. - OFFSET_Y ; max_y = MAP_HEIGHT/2.;},
-            3 => { // lower right
-                min_x = MAP_WIDTH/2. - OFFSET_X; max_x = MAP_WIDTH/2.; min_y = -MAP_HEIGHT/2.; max_y = -MAP_HEIGHT/2. + OFFSET_Y;},
-            4 => { // lower left
-                min_x = -MAP_WIDTH/2.; max_x = -MAP_WIDTH/2. + OFFSET_X; min_y = -MAP_HEIGHT/2.; max_y = -MAP_HEIGHT/2. + OFFSET_Y;},
-            _ => {},
-        }
+    for (race, race_config) in races.iter() {
         // spawn at -10 Z so sprites are in front of it?
-        let homebase_x = rng.gen_range(min_x..max_x);
-        let homebase_y = rng.gen_range(min_y..max_y);
-        let homebase_pos: Vec3 = Vec3::new(homebase_x, homebase_y, -10.);
-
-        let mut texture_handle: Handle<Image> = asset_server.load("textures/Homebases/blue_homebase.png");
-
-        let mut race: SpeciesRace = SpeciesRace::Red;
-        match i {
-            1 => {race = SpeciesRace::Blue; texture_handle = asset_server.load("textures/Homebases/blue_homebase.png")}
-            2 => {race = SpeciesRace::Red; texture_handle = asset_server.load("textures/Homebases/red_homebase.png")}
-            3 => {race = SpeciesRace::Yellow; texture_handle = asset_server.load("textures/Homebases/yellow_homebase.png")}
-            4 => {race = SpeciesRace::Green; texture_handle = asset_server.load("textures/Homebases/green_homebase.png")},
-            _ => continue,
-        };
-
-        commands.spawn((
-            SpriteBundle {
-                texture: texture_handle,
-                transform: Transform {
-                     translation: homebase_pos,
-                     rotation: Quat::default(),
-                     scale: Vec3::splat(2.),
-                },
-                ..default()
+        let homebase_xy = race_config.homebase_region.random_position(&mut *rng, &world);
+        let homebase_pos: Vec3 = homebase_xy.extend(HOMEBASE_Z);
+
+        let home = Homebase::new(homebase_pos, race, &config);
+        commands.spawn((home.sprite(&race_assets), home));
+    }
+}
+
+
+
+/// Species at home drop off what they brought and eat and drink from the stockpile when they need to. Also counts
+/// the population of every homebase. Every storage built at a homebase makes room for more food and water
+pub fn stock_homebases(
+    mut home_query: Query<&mut Homebase>,
+    mut species_query: Query<(&mut Species, &mut FoodDesire, &mut WaterDesire)>,
+    structures: Query<&Structure>,
+    config: Res<HomebaseConfig>,
+    engineering: Res<EngineeringConfig>,
+    hunger: Res<HungerConfig>,
+) {
+    for mut home in home_query.iter_mut() {
+        home.population = 0;
+    }
+
+    for (mut sp, mut food_des, mut water_des) in species_query.iter_mut() {
+        let Some(mut home) = home_query.iter_mut().find(|home| home.is_home_of(&sp)) else { continue; };
+        home.population += 1;
+        if !home.is_near(sp.position, &config) { continue; }
+
+        let (food_capacity, water_capacity) = home.capacity(&structures, &config, &engineering);
+
+        // drop off the food beyond a full stomach, the water that was carried back and the building materials
+        let surplus = (food_des.val - hunger.max_hunger).min(food_capacity - home.food);
+        if surplus > 0. {
+            food_des.val -= surplus;
+            home.food += surplus;
+        }
+        let water = water_des.carried.min(water_capacity - home.water).max(0.);
+        water_des.carried -= water;
+        home.water += water;
+        match sp.carrying.take() {
+            Some((Material::Wood, amount)) => home.wood += amount,
+            Some((Material::Stone, amount)) => home.stone += amount,
+            None => {},
+        }
+
+        // hungry or thirsty species fill up from the stockpile
+        if food_des.val <= 0. && home.food > 0. {
+            let food = (hunger.max_hunger - food_des.val).min(home.food);
+            food_des.val += food;
+            home.food -= food;
+        }
+        if water_des.val <= 0. && home.water > 0. {
+            let water = (water_des.spawn_val - water_des.val).min(home.water);
+            water_des.val += water;
+            home.water -= water;
+        }
+    }
+}
+
+
+
+/// Homebases with no health left are gone. The race that destroyed one takes the stockpile into its own nearest
+/// homebase. Enough survivors found a new homebase at the free site closest to them, otherwise they become nomads
+pub fn destroy_homebases(
+    mut home_query: Query<(Entity, &mut Homebase)>,
+    mut species_query: Query<&mut Species>,
+    structures: Query<&Structure>,
+    mut commands: Commands,
+    mut stats: EventWriter<StatisticsEvent>,
+    config: Res<HomebaseConfig>,
+    engineering: Res<EngineeringConfig>,
+    races: Res<Races>,
+    race_assets: Res<RaceAssets>,
+    world: Res<WorldConfig>,
+    nav_grid: Res<NavGrid>,
+    mut rng: ResMut<SimRng>,
+) {
+    let destroyed: Vec<(Entity, Homebase)> = home_query.iter()
+        .filter(|(_, home)| home.is_destroyed())
+        .map(|(e, home)| (e, home.clone()))
+        .collect();
+
+    for (e, ruin) in destroyed {
+        commands.entity(e).despawn();
+        let name = &races.get(ruin.species_race).name;
+
+        // the raiders carry off the stockpile
+        if let Some(raider) = ruin.last_raider {
+            let nearest = home_query.iter_mut()
+                .filter(|(other, home)| *other != e && home.species_race == raider && !home.is_destroyed())
+                .min_by(|(_, a), (_, b)| a.position.distance(ruin.position).total_cmp(&b.position.distance(ruin.position)));
+            if let Some((_, mut home)) = nearest {
+                let (food_capacity, water_capacity) = home.capacity(&structures, &config, &engineering);
+                home.food = (home.food + ruin.food).min(food_capacity);
+                home.water = (home.water + ruin.water).min(water_capacity);
+            }
+            stats.send(StatisticsEvent::new("homebase_destroyed", &format!("{} by {}", name, races.get(raider).name), ruin.population as f32));
+        } else {
+            stats.send(StatisticsEvent::new("homebase_destroyed", name, ruin.population as f32));
+        }
+
+        let survivors: Vec<Vec3> = species_query.iter()
+            .filter(|sp| ruin.is_home_of(sp))
+            .map(|sp| sp.position)
+            .collect();
+        if survivors.is_empty() { continue; }
+        let center = survivors.iter().sum::<Vec3>() / survivors.len() as f32;
+
+        let mut site: Option<Vec2> = None;
+        if survivors.len() as u32 >= config.relocate_population {
+            let taken: Vec<Vec2> = taken_sites(home_query.iter().map(|(_, home)| home), species_query.iter())
+                .into_iter()
+                .filter(|taken| *taken != ruin.position.xy())
+                .collect();
+            site = find_site(&mut rng, &world, &nav_grid, &config, &taken, |site| site.distance(center.xy()));
+        }
+
+        let new_home = site.map(|site| site.extend(HOMEBASE_Z));
+        for mut sp in species_query.iter_mut() {
+            if ruin.is_home_of(&sp) {
+                sp.homebase = new_home;
+            }
+        }
+        match new_home {
+            Some(position) => {
+                let home = Homebase::new(position, ruin.species_race, &config);
+                commands.spawn((home.sprite(&race_assets), home));
+                info!("{} moved to a new homebase at {:?}", name, position.xy());
+                stats.send(StatisticsEvent::new("homebase_relocated", name, survivors.len() as f32));
+            },
+            None => {
+                info!("{} lost its homebase, {} survivors are nomads now", name, survivors.len());
+                stats.send(StatisticsEvent::new("nomads", name, survivors.len() as f32));
             },
-            Homebase::new(homebase_pos, race)
-        ));
+        }
+    }
+}
+
 
 
+/// Where homebases are or are about to be: every standing homebase, and the sites colonists are on their way to
+pub fn taken_sites<'a>(homes: impl Iterator<Item = &'a Homebase>, species: impl Iterator<Item = &'a Species>) -> Vec<Vec2> {
+    let mut taken: Vec<Vec2> = homes.filter(|home| !home.is_destroyed()).map(|home| home.position.xy()).collect();
+    for home in species.filter_map(|sp| sp.homebase) {
+        if !taken.contains(&home.xy()) {
+            taken.push(home.xy());
+        }
     }
+    taken
+}
+
 
+/// The best of a few random sites that aren't blocked and keep `site_clearance` from the taken ones. Lower scores
+/// are better
+fn find_site(
+    rng: &mut SimRng,
+    world: &WorldConfig,
+    nav_grid: &NavGrid,
+    config: &HomebaseConfig,
+    taken: &[Vec2],
+    score: impl Fn(Vec2) -> f32,
+) -> Option<Vec2> {
+    let anywhere = HomebaseRegion { min: (0., 0.), max: (1., 1.) };
+    let mut best: Option<(Vec2, f32)> = None;
+    for _ in 0..config.site_candidates {
+        let candidate = anywhere.random_position(rng, world);
+        let free = !nav_grid.is_blocked_world(candidate)
+            && taken.iter().all(|taken| taken.distance(candidate) >= config.site_clearance);
+        if !free { continue; }
+        let candidate_score = score(candidate);
+        if best.is_none_or(|(_, best_score)| candidate_score < best_score) {
+            best = Some((candidate, candidate_score));
+        }
+    }
+    best.map(|(site, _)| site)
+}
+
+
+
+/// A homebase with more than `crowding_population` species sends a group of its adults off to found a colony at a
+/// free site close to water and food. The colonists take the site as their homebase and travel there
+pub fn split_crowded_homebases(
+    mut home_query: Query<(Entity, &mut Homebase)>,
+    mut species_query: Query<&mut Species>,
+    water_query: Query<&WaterSource>,
+    mut stats: EventWriter<StatisticsEvent>,
+    config: Res<HomebaseConfig>,
+    aging: Res<AgingConfig>,
+    races: Res<Races>,
+    food_locations: Res<FoodLocations>,
+    world: Res<WorldConfig>,
+    nav_grid: Res<NavGrid>,
+    mut rng: ResMut<SimRng>,
+    time: Res<Time>,
+) {
+    // close to water and food. When there is none of one of them left, only the other counts
+    let promise = |site: Vec2| {
+        let water = water_query.iter().map(|water| water.position.xy().distance(site)).fold(f32::INFINITY, f32::min);
+        let food = food_locations.position.iter().map(|food| food.distance(site)).fold(f32::INFINITY, f32::min);
+        [water, food].into_iter().filter(|distance| distance.is_finite()).sum::<f32>()
+    };
+
+    for (_, mut home) in home_query.iter_mut() {
+        home.split_cooldown = (home.split_cooldown - time.delta_seconds()).max(0.);
+    }
+    let crowded: Vec<Entity> = home_query.iter()
+        .filter(|(_, home)| !home.is_destroyed() && home.split_cooldown <= 0. && home.population > config.crowding_population)
+        .map(|(e, _)| e)
+        .collect();
+
+    for e in crowded {
+        let taken = taken_sites(home_query.iter().map(|(_, home)| home), species_query.iter());
+        let Some(site) = find_site(&mut rng, &world, &nav_grid, &config, &taken, promise) else { continue; };
+        let Ok((_, mut home)) = home_query.get_mut(e) else { continue; };
+
+        let mut colonists = 0;
+        for mut sp in species_query.iter_mut() {
+            if colonists >= config.colony_size { break; }
+            if !home.is_home_of(&sp) || sp.stage(&aging) == LifeStage::Juvenile { continue; }
+            sp.homebase = Some(site.extend(HOMEBASE_Z));
+            colonists += 1;
+        }
+        home.population -= colonists;
+        home.split_cooldown = config.split_cooldown_sec;
+        info!("{} colonists of {} leave for {:?}", colonists, races.get(home.species_race).name, site);
+        stats.send(StatisticsEvent::new("colony_split", &races.get(home.species_race).name, colonists as f32));
+    }
+}
+
+
+
+/// Colonists found their homebase once the first of them reaches the site
+pub fn found_colonies(
+    home_query: Query<&Homebase>,
+    species_query: Query<&Species>,
+    mut commands: Commands,
+    mut stats: EventWriter<StatisticsEvent>,
+    config: Res<HomebaseConfig>,
+    races: Res<Races>,
+    race_assets: Res<RaceAssets>,
+) {
+    let mut founded: Vec<Vec3> = Vec::new();
+    for sp in species_query.iter() {
+        let Some(site) = sp.homebase else { continue; };
+        if founded.contains(&site) || home_query.iter().any(|home| home.is_home_of(sp)) { continue; }
+        if (sp.position.xy() - site.xy()).length() >= config.radius { continue; }
+
+        let colonists = species_query.iter().filter(|other| other.race == sp.race && other.homebase == Some(site)).count();
+        let home = Homebase::new(site, sp.race, &config);
+        commands.spawn((home.sprite(&race_assets), home));
+        founded.push(site);
+        info!("{} founded a colony at {:?}", races.get(sp.race).name, site.xy());
+        stats.send(StatisticsEvent::new("colony_founded", &races.get(sp.race).name, colonists as f32));
+    }
 }
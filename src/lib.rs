@@ -0,0 +1,41 @@
+//! The evolution simulation as a library. `SimulationPlugins` runs it headless, `RenderingPlugin` and
+//! `DebugUiPlugin` add the window, camera and debug menu the app uses
+
+pub mod species;
+pub mod my_utils;
+pub mod player;
+pub mod fps_counter;
+pub mod water_source;
+pub mod water_desire;
+pub mod health;
+pub mod food_desire;
+pub mod food_source;
+pub mod homebase;
+pub mod behavior;
+pub mod debug_ui;
+pub mod physics;
+pub mod fight;
+pub mod reproduce;
+pub mod statistics;
+pub mod weather;
+pub mod obstacle;
+pub mod navigation;
+pub mod flow_field;
+pub mod race;
+pub mod config;
+pub mod clock;
+pub mod sim_rng;
+pub mod snapshot;
+pub mod autosave;
+pub mod replay;
+pub mod headless;
+pub mod batch;
+pub mod termination;
+pub mod lineage;
+pub mod engineering;
+pub mod plugins;
+
+pub use plugins::*;
+
+pub const SCREEN_WIDTH: f32 = 1920.;
+pub const SCREEN_HEIGHT: f32 = 1080.;
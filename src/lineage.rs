@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use crate::clock::SimClock;
+use crate::config::WorldConfig;
+use crate::health::Died;
+use crate::race::*;
+use crate::species::Species;
+use crate::statistics::Statistics;
+
+const LINEAGES_FILE: &str = "lineages.csv";
+
+
+/// A species of the starting population and all of its descendants
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Lineage {
+    pub race: SpeciesRace,
+    pub born: u32, // including the founder
+    pub died: u32,
+    pub generations: u32, // the deepest generation so far, the founder is generation 0
+    pub extinct_at: Option<f64>, // simulated seconds
+}
+
+impl Lineage {
+    pub fn alive(&self) -> u32 {
+        self.born - self.died
+    }
+}
+
+
+/// Every lineage of the run, indexed by `Species::lineage`. Written to `<output_dir>/lineages.csv` every time the
+/// population is sampled
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Lineages {
+    pub list: Vec<Lineage>,
+}
+
+impl Lineages {
+    /// Start a new lineage with a species of the starting population, returns its index
+    pub fn found(&mut self, race: SpeciesRace) -> u32 {
+        self.list.push(Lineage { race, born: 1, died: 0, generations: 0, extinct_at: None });
+        (self.list.len() - 1) as u32
+    }
+
+    pub fn born(&mut self, child: &Species) {
+        if let Some(lineage) = self.list.get_mut(child.lineage as usize) {
+            lineage.born += 1;
+            lineage.generations = lineage.generations.max(child.generation);
+        }
+    }
+
+    fn write(&self, races: &Races, path: &PathBuf) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "lineage,race,born,died,alive,generations,extinct_at")?;
+        for (i, lineage) in self.list.iter().enumerate() {
+            let extinct_at = lineage.extinct_at.map_or(String::new(), |t| format!("{:.2}", t));
+            writeln!(writer, "{},{},{},{},{},{},{}", i, races.get(lineage.race).name, lineage.born, lineage.died, lineage.alive(), lineage.generations, extinct_at)?;
+        }
+        writer.flush()
+    }
+}
+
+
+
+pub fn record_lineage_deaths(
+    mut died: EventReader<Died>,
+    mut lineages: ResMut<Lineages>,
+    clock: Res<SimClock>,
+) {
+    for died in died.read() {
+        if let Some(lineage) = lineages.list.get_mut(died.lineage as usize) {
+            lineage.died += 1;
+            if lineage.alive() == 0 {
+                lineage.extinct_at = Some(clock.elapsed);
+            }
+        }
+    }
+}
+
+
+
+/// Rewrite the lineages file along with every population sample of the statistics
+pub fn write_lineages(
+    stats: Res<Statistics>,
+    lineages: Res<Lineages>,
+    races: Res<Races>,
+    world: Res<WorldConfig>,
+) {
+    if !stats.sample_timer.just_finished() { return; }
+
+    let path = PathBuf::from(&world.output_dir).join(LINEAGES_FILE);
+    if let Err(e) = lineages.write(&races, &path) {
+        warn!("Could not write {:?}: {}", path, e);
+    }
+}
@@ -1,110 +1,172 @@
 use bevy::prelude::*;
 use bevy::window::WindowResolution;
-use health::{kill_zero_health, damage_low_stats};
-use homebase::create_homebases;
-use bevy_egui::EguiPlugin;
-use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
-
-use species::*;
-use my_utils::*;
-use fps_counter::*;
-use water_source::*;
-use water_desire::*;
-use food_desire::*;
-use food_source::*;
-use debug_ui::*;
-use behavior::*;
-use fight::*;
-use health::*;
-use reproduce::*;
-
-mod species;
-mod my_utils;
-mod player;
-mod fps_counter;
-mod water_source;
-mod water_desire;
-mod health;
-mod food_desire;
-mod food_source;
-mod homebase;
-mod behavior;
-mod debug_ui;
-mod physics;
-mod fight;
-mod reproduce;
-
-pub const SCREEN_WIDTH: f32 = 1920.;
-pub const SCREEN_HEIGHT: f32 = 1080.;
-pub const MAP_WIDTH: f32 = 4000.;
-pub const MAP_HEIGHT: f32 = 4000.;
+use std::path::PathBuf;
+
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::config::*;
+use evolution_sim_bevy::headless::HeadlessPlugin;
+use evolution_sim_bevy::race::Races;
+use evolution_sim_bevy::replay::*;
+use evolution_sim_bevy::sim_rng::SimRng;
+use evolution_sim_bevy::snapshot::*;
 
 
 fn main() {
 
-    App::new()
-        .add_plugins(
-            DefaultPlugins
-            .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Evolution Sim v1.0".into(),
-                        resolution: {WindowResolution::new(SCREEN_WIDTH, SCREEN_HEIGHT)},
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("batch") {
+        if let Err(e) = batch::run_batch(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let headless = args.iter().any(|a| a == "--headless");
+    let time_limit_sec = match arg_value(&args, &["--time-limit"]).and_then(|l| l.map(|l| l.parse::<f64>().map_err(|e| format!("--time-limit: {}", e))).transpose()) {
+        Ok(limit) => limit,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let config_path = match config_path_from_args(&args) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let resume_path = match arg_value(&args, &["--load"]) {
+        Ok(path) => path.map(PathBuf::from),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let snapshot = resume_path.as_ref().map(|path| match WorldSnapshot::load(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Could not load the snapshot: {}", e);
+            std::process::exit(1);
+        },
+    });
+
+    let replay_file = match arg_value(&args, &["--replay"]) {
+        Ok(path) => path.map(|path| match ReplayFile::load(&PathBuf::from(path)) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Could not load the replay: {}", e);
+                std::process::exit(1);
+            },
+        }),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    if snapshot.is_some() && replay_file.is_some() {
+        eprintln!("--load and --replay can not be used together");
+        std::process::exit(1);
+    }
+
+    // a resumed run uses the config it was saved with, unless another one is given with --config
+    let explicit_config = matches!(arg_value(&args, &["--config", "-c"]), Ok(Some(_)));
+    // a replay always uses the config it was recorded with
+    let (mut config, config_path) = match (&snapshot, explicit_config) {
+        _ if replay_file.is_some() => (replay_file.as_ref().unwrap().config.clone(), None),
+        (Some(snapshot), false) => (snapshot.config.clone(), None),
+        _ => (load_config(&config_path), config_path),
+    };
+    if time_limit_sec.is_some() {
+        config.termination.time_limit_sec = time_limit_sec;
+    }
+    if headless && !config.termination.any() {
+        eprintln!("Warning: no termination conditions are set, the headless run will not end by itself");
+    }
+
+    let races = match Races::load(&config.world.races_file) {
+        Ok(races) => races,
+        Err(e) => {
+            eprintln!("Could not load the races: {}", e);
+            std::process::exit(1);
+        },
+    };
+    if let Some(snapshot) = &snapshot {
+        if let Err(e) = snapshot.check_races(&races) {
+            eprintln!("Can not resume: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(file) = &replay_file {
+        if let Err(e) = file.check_races(&races) {
+            eprintln!("Can not play back: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let rng = match (&snapshot, &replay_file) {
+        (Some(snapshot), _) => snapshot.rng.clone(),
+        (_, Some(file)) => SimRng::new(file.seed),
+        _ => SimRng::from_seed(config.world.seed),
+    };
+    let replay = match replay_file {
+        Some(file) => Replay::playback(file),
+        None => Replay::record(rng.seed, config.clone()),
+    };
+
+    let mut app = App::new();
+    match headless {
+        true => {
+            app.add_plugins(HeadlessPlugin);
+        },
+        false => {
+            app.add_plugins(
+                DefaultPlugins
+                .set(WindowPlugin {
+                        primary_window: Some(Window {
+                            title: "Evolution Sim v1.0".into(),
+                            resolution: {WindowResolution::new(SCREEN_WIDTH, SCREEN_HEIGHT)},
+                            ..default()
+                        }),
                         ..default()
-                    }),
-                    ..default()
-                })
-            .set(ImagePlugin::default_nearest())
-        )
-        .init_resource::<UiState>()
-        .init_resource::<FoodLocations>()
-        .add_event::<Reproduce>()
-        // PLUGINS
-        .add_plugins(FrameTimeDiagnosticsPlugin::default())
-        .add_plugins(EguiPlugin)
-
-        .add_systems(Startup, 
-            (setup, setup_fps_counter, create_homebases, spawn_water_sources, spawn_food_sources))
-
-        .add_systems(PostStartup, initial_species_group_spawn)
-
-        .add_systems(PreUpdate, 
-            (camera_movement, key_h_go_home, fps_text_update_system, fps_counter_showhide, zoom_system))
-
-        .add_systems(Update, 
-            (debug_menu_ui, damage_low_stats, update_reproduction, react_to_reproduction_event, update_hunger, update_water_desire, behaviors, spawn_food_replenish, fight_species, fade_out_blood))
-
-        .add_systems(PostUpdate,
-            (despawn_all_enemies, kill_zero_health, debug_single_species))
-        // EVENTS
-        // .add_systems(Update, 
-        //     (trigger_event_single_species, react_to_event_single_species))
-        // .add_event::<Reproduce>()
-
-        .run();
+                    })
+                .set(ImagePlugin::default_nearest())
+            );
+        },
+    }
+
+    // kept by `CorePlugin`, they decide where the run starts from
+    app.insert_resource(ConfigPath(config_path))
+        .insert_resource(rng)
+        .insert_resource(replay)
+        .add_plugins(SimulationPlugins { config, races });
+    if !headless {
+        app.add_plugins((RenderingPlugin, DebugUiPlugin));
+    }
+
+    if let Some(path) = resume_path {
+        app.insert_resource(ResumeFrom(path.clone()));
+        app.world.send_event(LoadSnapshot(path));
+    }
+
+    app.run();
 }
 
 
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-) {
-
-    let origin_texture = asset_server.load("textures/origin_marker_slim.png");
-
-    // create an origin sprite marker
-    commands.spawn((
-        SpriteBundle{
-            texture: origin_texture,
-            transform: Transform {
-                 translation: Vec3::new(0., 0., 0.),
-                 rotation: Quat::default(),
-                 scale: Vec3::splat(1.0),
+fn load_config(config_path: &Option<PathBuf>) -> SimConfig {
+    match config_path {
+        Some(path) => match SimConfig::load(path) {
+            Ok(config) => config,
+            Err(errors) => {
+                eprintln!("Invalid config {:?}:", path);
+                for e in errors {
+                    eprintln!("  {}", e);
+                }
+                std::process::exit(1);
             },
-            ..default()},
-    ));
-
-    commands.spawn(Camera2dBundle::default());
-    
+        },
+        None => SimConfig::default(),
+    }
 }
-
@@ -1,5 +1,6 @@
 use bevy::{prelude::*};
-use crate::{species::*, water_desire::WaterDesire, health::Health, food_desire::*};
+use crate::{species::*, water_desire::WaterDesire, health::Health, food_desire::*, race::*, replay::*};
+use bevy::utils::HashSet;
 use bevy::input::mouse::MouseWheel;
 use bevy::window::PrimaryWindow;
 use std::f32::consts::PI;
@@ -9,15 +10,38 @@ const MIN_ZOOM: f32 = 0.01;
 const MAX_ZOOM: f32 = 5.0;
 
 
-pub fn despawn_all_enemies(
+
+pub fn setup(
     mut commands: Commands,
-    query: Query<Entity, With<Species>>,
+    asset_server: Res<AssetServer>,
+) {
+
+    let origin_texture = asset_server.load("textures/origin_marker_slim.png");
+
+    // create an origin sprite marker
+    commands.spawn((
+        SpriteBundle{
+            texture: origin_texture,
+            transform: Transform {
+                 translation: Vec3::new(0., 0., 0.),
+                 rotation: Quat::default(),
+                 scale: Vec3::splat(1.0),
+            },
+            ..default()},
+    ));
+
+    commands.spawn(Camera2dBundle::default());
+    
+}
+
+
+// the species are despawned at the start of the next simulation tick, so it is part of the replay recording
+pub fn despawn_all_enemies(
+    mut pending: ResMut<PendingInputs>,
     keyboard_input: Res<Input<KeyCode>>,
 ) {
     if keyboard_input.pressed(KeyCode::Q) {
-        for entity in query.iter() {
-            commands.entity(entity).despawn();
-        }
+        pending.push(SimInput::DespawnAll);
     }
 }
 
@@ -78,48 +102,17 @@ pub fn key_h_go_home(
 
 pub fn debug_single_species(
     query: Query<(Entity, &Species, &Transform, &WaterDesire, &Health, &FoodDesire)>,
+    races: Res<Races>,
 ) {
-    let mut chose_blue: bool = false;
-    let mut chose_red: bool = false;
-    let mut chose_green: bool = false;
-    let mut chose_yellow: bool = false;
+    let mut chosen: HashSet<SpeciesRace> = HashSet::new();
     for (entity, spec, transform, water_desire, health, food_desire) in query.iter() {
         // get only the first species in each race
-        if spec.race == SpeciesRace::Blue {
-            if !chose_blue {
-                //  info!("Blue vel {}, acc: {}, pos: {}", spec.velocity.length(), spec.acceleration.length(), spec.position);
-                // info!("Blue health: {}", health.val);
-                // if water_desire.amount < 0.0 {info!("Blue is out of water")}
-                // info!("Blue water: {}", water_desire.curr_val);
-                // info!("Blue hunger: {}", food_desire.curr_val);
-                // info!("Elapsed secs: {}", water_desire.timer.elapsed_secs());
-                // let angle = f32::atan2(spec.velocity.x, spec.velocity.y);
-                // info!("angle : {}", angle);
-
-                // info!("x: {}, y: {}, angle: {}", spec.velocity.x, spec.velocity.y, angle);
-            } 
-            chose_blue = true;
-        }
-        if spec.race == SpeciesRace::Red {
-            if !chose_red {
-                // info!("Red {}", spec.position);
-            }
-            chose_red = true;
-        }
-        if spec.race == SpeciesRace::Green {
-            if !chose_green {
-                // info!("Green {}", spec.position);
-            }
-            chose_green = true;
-        }
-        if spec.race == SpeciesRace::Yellow {
-            if !chose_yellow {
-                // info!("Yellow water amount: {}", water_desire.amount);
-                // info!("Yellow perception radius: {}", spec.perception_radius);
-                // info!("Yellow health: {}", health.val)
-                //info!("Yellow acc: {}", spec.acceleration.length());
-            }
-            chose_yellow = true;
+        if chosen.insert(spec.race) {
+            //  info!("{} vel {}, acc: {}, pos: {}", races.get(spec.race).name, spec.velocity.length(), spec.acceleration.length(), spec.position);
+            // info!("{} health: {}", races.get(spec.race).name, health.val);
+            // info!("{} water: {}", races.get(spec.race).name, water_desire.val);
+            // info!("{} hunger: {}", races.get(spec.race).name, food_desire.val);
+            // info!("{} perception radius: {}", races.get(spec.race).name, spec.perception_radius);
         }
     }
 }
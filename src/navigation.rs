@@ -0,0 +1,281 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use crate::config::WorldConfig;
+use crate::obstacle::Obstacle;
+use crate::species::Species;
+use crate::flow_field::FlowFields;
+
+pub const NAV_CELL_SIZE: f32 = 40.;
+const PATHS_PER_UPDATE: usize = 50; // limit the number of A* searches each update so a crowd of new targets doesn't stall a frame
+const WAYPOINT_REACHED_DIST: f32 = NAV_CELL_SIZE * 0.75;
+const RETARGET_DIST: f32 = NAV_CELL_SIZE; // recompute the path when the target moves further than this
+
+// 8-connected grid, diagonal moves cost sqrt(2)
+const NEIGHBORS: [(i32, i32, f32); 8] = [
+    (1, 0, 1.), (-1, 0, 1.), (0, 1, 1.), (0, -1, 1.),
+    (1, 1, std::f32::consts::SQRT_2), (1, -1, std::f32::consts::SQRT_2),
+    (-1, 1, std::f32::consts::SQRT_2), (-1, -1, std::f32::consts::SQRT_2),
+];
+
+
+/// Coarse grid over the whole map marking which cells are blocked by obstacles.
+/// `version` increases every time the blocked cells change, so anything cached from it knows when to recompute
+#[derive(Resource, Serialize, Deserialize, Clone)]
+pub struct NavGrid {
+    pub width: i32,
+    pub height: i32,
+    pub cell_size: f32,
+    pub origin: Vec2,
+    pub blocked: Vec<bool>,
+    pub version: u32,
+}
+
+impl FromWorld for NavGrid {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<WorldConfig>();
+        Self::new(config.map_width, config.map_height)
+    }
+}
+
+impl NavGrid {
+    pub fn new(map_width: f32, map_height: f32) -> Self {
+        let width = (map_width / NAV_CELL_SIZE).ceil() as i32;
+        let height = (map_height / NAV_CELL_SIZE).ceil() as i32;
+        Self {
+            width,
+            height,
+            cell_size: NAV_CELL_SIZE,
+            origin: Vec2::new(-map_width/2., -map_height/2.),
+            blocked: vec![false; (width * height) as usize],
+            version: 0,
+        }
+    }
+
+    pub fn world_to_cell(&self, pos: Vec2) -> Option<IVec2> {
+        let rel = (pos - self.origin) / self.cell_size;
+        let cell = IVec2::new(rel.x.floor() as i32, rel.y.floor() as i32);
+        if self.in_bounds(cell) { Some(cell) } else { None }
+    }
+
+    pub fn cell_to_world(&self, cell: IVec2) -> Vec2 {
+        self.origin + (cell.as_vec2() + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    pub fn in_bounds(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    pub fn index(&self, cell: IVec2) -> usize {
+        (cell.y * self.width + cell.x) as usize
+    }
+
+    pub fn is_blocked(&self, cell: IVec2) -> bool {
+        self.in_bounds(cell) && self.blocked[self.index(cell)]
+    }
+
+    /// Anything off the grid counts as walkable
+    pub fn is_blocked_world(&self, pos: Vec2) -> bool {
+        match self.world_to_cell(pos) {
+            Some(cell) => self.blocked[self.index(cell)],
+            None => false,
+        }
+    }
+
+    /// 8-connected neighbors that can be walked to without cutting the corner of an obstacle, with the cost of the move
+    pub fn walkable_neighbors(&self, cell: IVec2) -> impl Iterator<Item = (IVec2, f32)> + '_ {
+        NEIGHBORS.into_iter().filter_map(move |(dx, dy, cost)| {
+            let next = cell + IVec2::new(dx, dy);
+            if !self.in_bounds(next) || self.is_blocked(next) { return None; }
+            if dx != 0 && dy != 0
+                && (self.is_blocked(cell + IVec2::new(dx, 0)) || self.is_blocked(cell + IVec2::new(0, dy))) {
+                return None;
+            }
+            Some((next, cost))
+        })
+    }
+
+    /// A* from start to goal over the walkable cells. Returns the world position of the center of each cell along
+    /// the way, excluding the start cell and with the exact goal as the last point. None if the goal can not be reached
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<Vec2>> {
+        let start_cell = self.world_to_cell(start)?;
+        let goal_cell = self.world_to_cell(goal)?;
+        if self.is_blocked(goal_cell) { return None; }
+        if start_cell == goal_cell { return Some(vec![goal]); }
+
+        let n = (self.width * self.height) as usize;
+        let mut g_score = vec![f32::INFINITY; n];
+        let mut came_from: Vec<Option<usize>> = vec![None; n];
+        let mut closed = vec![false; n];
+        let mut open = BinaryHeap::new();
+
+        let heuristic = |c: IVec2| {
+            // octile distance, admissible for 8-connected moves
+            let d = (c - goal_cell).abs();
+            let (lo, hi) = (d.x.min(d.y) as f32, d.x.max(d.y) as f32);
+            hi + (std::f32::consts::SQRT_2 - 1.) * lo
+        };
+
+        g_score[self.index(start_cell)] = 0.;
+        open.push(OpenNode { cell: start_cell, f: heuristic(start_cell) });
+
+        while let Some(OpenNode { cell, .. }) = open.pop() {
+            let i = self.index(cell);
+            if closed[i] { continue; }
+            closed[i] = true;
+
+            if cell == goal_cell {
+                let mut path = vec![goal];
+                let mut cur = came_from[i];
+                while let Some(prev) = cur {
+                    if prev == self.index(start_cell) { break; }
+                    let prev_cell = IVec2::new(prev as i32 % self.width, prev as i32 / self.width);
+                    path.push(self.cell_to_world(prev_cell));
+                    cur = came_from[prev];
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for (next, cost) in self.walkable_neighbors(cell) {
+                let j = self.index(next);
+                let tentative = g_score[i] + cost;
+                if tentative < g_score[j] {
+                    g_score[j] = tentative;
+                    came_from[j] = Some(i);
+                    open.push(OpenNode { cell: next, f: tentative + heuristic(next) });
+                }
+            }
+        }
+        None
+    }
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+struct OpenNode {
+    cell: IVec2,
+    f: f32,
+}
+
+impl Eq for OpenNode {}
+
+// reversed so the BinaryHeap pops the lowest f score first
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+
+/// The path a species is following towards its `target_pos`
+#[derive(Component, Serialize, Deserialize, Clone, Default)]
+pub struct NavPath {
+    pub target: Vec3,
+    pub waypoints: Vec<Vec2>,
+    pub index: usize,
+    pub grid_version: u32,
+    pub needs_update: bool,
+}
+
+impl NavPath {
+    /// Direction to steer in to get to the target. Follows the waypoints if the path leads to this target,
+    /// otherwise steers straight at it
+    pub fn steer_direction(&self, position: Vec3, target: Vec3) -> Vec3 {
+        let aim = match self.waypoints.get(self.index) {
+            Some(waypoint) if (self.target - target).length() < RETARGET_DIST => *waypoint,
+            _ => target.xy(),
+        };
+        (aim - position.xy()).extend(0.).normalize_or_zero()
+    }
+}
+
+
+
+/// Mark every cell that overlaps an obstacle. Only rebuilds when obstacles are added or removed, and only bumps the
+/// version when that actually changed a cell, e.g. not when a snapshot puts back the same rocks
+pub fn rebuild_nav_grid(
+    mut grid: ResMut<NavGrid>,
+    obstacles: Query<&Obstacle>,
+    added: Query<(), Added<Obstacle>>,
+    mut removed: RemovedComponents<Obstacle>,
+) {
+    if added.is_empty() && removed.read().count() == 0 { return; }
+
+    let mut blocked = vec![false; grid.blocked.len()];
+    for obstacle in obstacles.iter() {
+        let min = obstacle.position.xy() - Vec2::splat(obstacle.half_size);
+        let max = obstacle.position.xy() + Vec2::splat(obstacle.half_size);
+        // shrink slightly so an obstacle that exactly fills one cell doesn't also block its neighbors
+        let (Some(min_cell), Some(max_cell)) = (
+            grid.world_to_cell(min + Vec2::splat(0.01)),
+            grid.world_to_cell(max - Vec2::splat(0.01)),
+        ) else { continue; };
+        for x in min_cell.x..=max_cell.x {
+            for y in min_cell.y..=max_cell.y {
+                blocked[grid.index(IVec2::new(x, y))] = true;
+            }
+        }
+    }
+    if blocked != grid.blocked {
+        grid.blocked = blocked;
+        grid.version += 1;
+    }
+}
+
+
+
+/// Recompute paths for species whose target moved, whose path ran out, or whose grid is out of date.
+/// Targets that have a flow field (homebases and water) are steered to with that instead
+pub fn update_nav_paths(
+    grid: Res<NavGrid>,
+    flow_fields: Res<FlowFields>,
+    mut query: Query<(&Species, &mut NavPath)>,
+) {
+    let mut searches = 0;
+    for (sp, mut path) in query.iter_mut() {
+
+        if flow_fields.towards(sp.target_pos).is_some() {
+            if !path.waypoints.is_empty() {
+                path.waypoints.clear();
+            }
+            continue;
+        }
+
+        // advance along the path
+        if let Some(waypoint) = path.waypoints.get(path.index) {
+            if (*waypoint - sp.position.xy()).length() < WAYPOINT_REACHED_DIST {
+                path.index += 1;
+            }
+        }
+
+        // a path that ran out before reaching the target means the species got pushed off it. An empty path means
+        // the target can't be reached, don't search again until the target or the grid changes
+        let ran_out = !path.waypoints.is_empty()
+            && path.index >= path.waypoints.len()
+            && (sp.target_pos.xy() - sp.position.xy()).length() > WAYPOINT_REACHED_DIST;
+        let stale = path.grid_version != grid.version
+            || (path.target - sp.target_pos).length() > RETARGET_DIST
+            || ran_out;
+        if stale {
+            path.needs_update = true;
+        }
+
+        if !path.needs_update || searches >= PATHS_PER_UPDATE { continue; }
+        searches += 1;
+
+        path.waypoints = grid.find_path(sp.position.xy(), sp.target_pos.xy()).unwrap_or_default();
+        path.index = 0;
+        path.target = sp.target_pos;
+        path.grid_version = grid.version;
+        path.needs_update = false;
+    }
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+use crate::config::WorldConfig;
+use crate::homebase::Homebase;
+use crate::water_source::WaterSource;
+use crate::navigation::NAV_CELL_SIZE;
+
+const ROCK_NOISE_SEED: u32 = 7;
+const ROCK_NOISE_SCALE: f64 = 0.08;
+const ROCK_THRESHOLD: f64 = 0.5; // noise above this becomes rock. Clusters of rocks form cliffs
+const HOMEBASE_CLEARANCE: f32 = 300.;
+const WATER_CLEARANCE: f32 = 200.;
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObstacleConfig {
+    pub rock_threshold: f64,
+    pub homebase_clearance: f32,
+    pub water_clearance: f32,
+}
+
+impl Default for ObstacleConfig {
+    fn default() -> Self {
+        Self {
+            rock_threshold: ROCK_THRESHOLD,
+            homebase_clearance: HOMEBASE_CLEARANCE,
+            water_clearance: WATER_CLEARANCE,
+        }
+    }
+}
+
+
+/// Something solid the species can not walk through. Obstacles are squares
+#[derive(Component)]
+pub struct Obstacle {
+    pub position: Vec3,
+    pub half_size: f32,
+}
+
+impl Obstacle {
+    pub fn new(position: Vec3, half_size: f32) -> Self {
+        Self {
+            position,
+            half_size,
+        }
+    }
+}
+
+
+
+/// A rock filling one navigation cell
+pub fn rock_bundle(position: Vec3) -> (SpriteBundle, Obstacle) {
+    (
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.4, 0.38, 0.35),
+                custom_size: Some(Vec2::splat(NAV_CELL_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_translation(position),
+            ..default()
+        },
+        Obstacle::new(position, NAV_CELL_SIZE / 2.),
+    )
+}
+
+
+
+/// Generate rocks and cliffs from a Perlin noise surface over the whole map, one rock per navigation cell.
+/// Runs after the homebases and water sources exist so they are kept clear
+pub fn spawn_obstacles(
+    mut commands: Commands,
+    home_query: Query<&Homebase>,
+    water_query: Query<&WaterSource>,
+    world: Res<WorldConfig>,
+    config: Res<ObstacleConfig>,
+) {
+    let perlin = Perlin::new(ROCK_NOISE_SEED);
+    let n_x = (world.map_width / NAV_CELL_SIZE) as i32;
+    let n_y = (world.map_height / NAV_CELL_SIZE) as i32;
+
+    for x in 0..n_x {
+        for y in 0..n_y {
+            // offset by half a cell so the noise is not sampled on its integer lattice, where it is always 0
+            let value = perlin.get([(x as f64 + 0.5) * ROCK_NOISE_SCALE, (y as f64 + 0.5) * ROCK_NOISE_SCALE]);
+            if value < config.rock_threshold { continue; }
+
+            let position = Vec3::new(
+                -world.map_width/2. + (x as f32 + 0.5) * NAV_CELL_SIZE,
+                -world.map_height/2. + (y as f32 + 0.5) * NAV_CELL_SIZE,
+                -1.,
+            );
+
+            let near_home = home_query.iter().any(|h| (h.position.xy() - position.xy()).length() < config.homebase_clearance);
+            let near_water = water_query.iter().any(|w| (w.position.xy() - position.xy()).length() < config.water_clearance);
+            if near_home || near_water { continue; }
+
+            commands.spawn(rock_bundle(position));
+        }
+    }
+}
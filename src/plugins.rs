@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+use bevy::app::PluginGroupBuilder;
+use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
+use bevy_egui::EguiPlugin;
+use crate::autosave::*;
+use crate::behavior::*;
+use crate::clock::*;
+use crate::config::*;
+use crate::debug_ui::*;
+use crate::engineering::*;
+use crate::fight::*;
+use crate::flow_field::*;
+use crate::food_desire::*;
+use crate::food_source::*;
+use crate::fps_counter::*;
+use crate::health::*;
+use crate::homebase::*;
+use crate::lineage::*;
+use crate::my_utils::*;
+use crate::navigation::*;
+use crate::obstacle::*;
+use crate::race::*;
+use crate::replay::*;
+use crate::reproduce::*;
+use crate::sim_rng::*;
+use crate::snapshot::*;
+use crate::species::*;
+use crate::statistics::*;
+use crate::termination::*;
+use crate::water_desire::*;
+use crate::water_source::*;
+use crate::weather::*;
+
+
+/// Everything the simulation needs to run headless, without a window. Add `RenderingPlugin` and `DebugUiPlugin`
+/// on top for the interactive app
+pub struct SimulationPlugins {
+    pub config: SimConfig,
+    pub races: Races,
+}
+
+impl PluginGroup for SimulationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CorePlugin { config: self.config, races: self.races })
+            .add(NeedsPlugin)
+            .add(ReproductionPlugin)
+            .add(WorldPlugin)
+            .add(BehaviorPlugin)
+            .add(ColonyPlugin)
+            .add(CombatPlugin)
+    }
+}
+
+
+
+/// The config, the simulation state and the fixed tick: clock, random numbers, statistics, snapshots, replays,
+/// autosave and termination. Every other simulation plugin needs this one.
+/// A `SimRng`, `Replay` or `ConfigPath` inserted before the plugin is kept, so a run can start from a given seed,
+/// snapshot or recording
+pub struct CorePlugin {
+    pub config: SimConfig,
+    pub races: Races,
+}
+
+impl Plugin for CorePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<ConfigPath>() {
+            app.insert_resource(ConfigPath(None));
+        }
+        if !app.world.contains_resource::<SimRng>() {
+            app.insert_resource(SimRng::from_seed(self.config.world.seed));
+        }
+        if !app.world.contains_resource::<Replay>() {
+            let seed = app.world.resource::<SimRng>().seed;
+            app.insert_resource(Replay::record(seed, self.config.clone()));
+        }
+
+        self.config.insert_resources(app);
+        app
+            .insert_resource(self.races.clone())
+            .insert_resource(Time::<Fixed>::from_hz(SIM_TICK_HZ))
+            .insert_resource(UiState::new(&self.config.movement))
+            .init_resource::<SimClock>()
+            .init_resource::<RaceAssets>()
+            .init_resource::<FoodLocations>()
+            .init_resource::<Weather>()
+            .init_resource::<NavGrid>()
+            .init_resource::<ConfigWatcher>()
+            .init_resource::<Autosave>()
+            .init_resource::<PendingInputs>()
+            .init_resource::<Termination>()
+            .init_resource::<Lineages>()
+            .add_event::<StatisticsEvent>()
+            .add_event::<Died>()
+            .add_event::<SaveSnapshot>()
+            .add_event::<LoadSnapshot>()
+            .add_event::<SaveReplay>()
+            .add_event::<ScrubTo>()
+
+            .configure_sets(FixedUpdate, SimSet.run_if(sim_should_step).run_if(run_active))
+            .configure_sets(FixedUpdate, (
+                SimStep::Inputs,
+                SimStep::Needs,
+                SimStep::Reproduction,
+                SimStep::Environment,
+                SimStep::Movement,
+                SimStep::Colony,
+                SimStep::Combat,
+                SimStep::Bookkeeping,
+            ).chain().in_set(SimSet))
+
+            .add_systems(PreStartup, load_race_assets)
+
+            .add_systems(Startup, (setup_statistics, dump_effective_config))
+
+            .add_systems(PreUpdate,
+                (restart_world, scrub_replay, save_snapshot, load_snapshot, save_replay).chain())
+
+            .add_systems(FixedUpdate,
+                (capture_replay_checkpoint, apply_replay_inputs).chain().in_set(SimStep::Inputs))
+
+            .add_systems(FixedUpdate,
+                (check_termination, record_lineage_deaths, write_statistics, write_lineages, autosave, tick_sim_clock).chain().in_set(SimStep::Bookkeeping))
+
+            .add_systems(Update, (finish_scrub, hot_reload_config));
+    }
+}
+
+
+
+/// Age, hunger, thirst, the damage from going without or from the weather, and healing
+pub struct NeedsPlugin;
+
+impl Plugin for NeedsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate,
+            (grow_older, damage_low_stats, storm_damage, regenerate_health, update_hunger, update_water_desire).chain().in_set(SimStep::Needs));
+    }
+}
+
+
+
+pub struct ReproductionPlugin;
+
+impl Plugin for ReproductionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Reproduce>()
+            .add_systems(FixedUpdate,
+                (update_reproduction, react_to_reproduction_event).chain().in_set(SimStep::Reproduction));
+    }
+}
+
+
+
+/// Generates the world at startup, and runs the environment: weather, water and food
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // chained, they all draw from the same random numbers in the same order. The species and obstacles need
+            // the homebases and water sources to be spawned already, the resource nodes the obstacles
+            .add_systems(GenerateWorld,
+                (create_homebases, spawn_water_sources, spawn_food_sources, apply_deferred, initial_species_group_spawn, spawn_obstacles, apply_deferred, spawn_resource_nodes).chain())
+
+            // a resumed run gets its world from the snapshot instead
+            .add_systems(Startup, generate_world.run_if(fresh_world))
+
+            .add_systems(FixedUpdate,
+                (update_weather, flood_water_sources, refill_water_sources, wither_food, decay_food, spawn_food_replenish).chain().in_set(SimStep::Environment));
+    }
+}
+
+
+
+/// Steering and path finding
+pub struct BehaviorPlugin;
+
+impl Plugin for BehaviorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlowFields>()
+            .add_systems(FixedUpdate,
+                (rebuild_nav_grid, update_flow_fields, update_nav_paths, steer_species, behaviors).chain().in_set(SimStep::Movement));
+    }
+}
+
+
+
+/// The homebases as the colonies' stores: stockpiles and population, what happens when one is destroyed, new
+/// colonies split off crowded ones, and the structures the builders put up around them
+pub struct ColonyPlugin;
+
+impl Plugin for ColonyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (destroy_homebases, destroy_structures, found_colonies, split_crowded_homebases, stock_homebases, build_structures).chain().in_set(SimStep::Colony));
+    }
+}
+
+
+
+/// Fights, raids on homebases and structures, towers, deaths and the blood and corpses they leave behind
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate,
+            (fight_species, raid_homebases, fire_towers, kill_zero_health, feed_killers, spawn_remains, fade_out_blood).chain().in_set(SimStep::Combat));
+    }
+}
+
+
+
+/// Camera, fps counter and the debug gizmos. Needs a window
+pub struct RenderingPlugin;
+
+impl Plugin for RenderingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(FrameTimeDiagnosticsPlugin::default())
+            .add_systems(Startup, (setup, setup_fps_counter))
+            .add_systems(PreUpdate,
+                (camera_movement, key_h_go_home, fps_text_update_system, fps_counter_showhide, zoom_system))
+            .add_systems(Update, (draw_species_gizmos, draw_health_bars));
+    }
+}
+
+
+
+/// The debug menu, the replay timeline, the resume prompt and the keyboard shortcuts. Needs a window
+pub struct DebugUiPlugin;
+
+impl Plugin for DebugUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(EguiPlugin)
+            .init_resource::<ResumePrompt>()
+            .add_systems(Startup, offer_resume.run_if(fresh_world))
+            .add_systems(PreUpdate, (snapshot_keys, replay_keys))
+            .add_systems(Update, (debug_menu_ui, resume_prompt_ui, replay_timeline_ui))
+            .add_systems(PostUpdate, (despawn_all_enemies, debug_single_species));
+    }
+}
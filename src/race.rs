@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use crate::config::WorldConfig;
+use crate::species::LIFESPAN_SEC;
+
+const GENERATED_SPRITE_SIZE: u32 = 16;
+
+
+/// Index into `Races::list`
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct SpeciesRace(pub usize);
+
+
+/// Starting values for a trait are picked uniformly between min and max
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub struct TraitRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TraitRange {
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        if self.max > self.min { rng.gen_range(self.min..=self.max) } else { self.min }
+    }
+}
+
+impl TraitRange {
+    fn lifespan() -> Self {
+        Self { min: LIFESPAN_SEC, max: LIFESPAN_SEC }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if !self.min.is_finite() || !self.max.is_finite() {
+            return Err(format!("{} must be finite, got {}..{}", name, self.min, self.max));
+        }
+        if self.min > self.max {
+            return Err(format!("{} has its min {} above its max {}", name, self.min, self.max));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TraitRange {
+    fn default() -> Self {
+        Self { min: 1.0, max: 1.0 }
+    }
+}
+
+
+/// Rectangle the homebase is placed in, as fractions of the map. (0, 0) is the lower left corner, (1, 1) the upper right
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub struct HomebaseRegion {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl HomebaseRegion {
+    pub fn random_position(&self, rng: &mut impl Rng, world: &WorldConfig) -> Vec2 {
+        let fx = if self.max.0 > self.min.0 { rng.gen_range(self.min.0..self.max.0) } else { self.min.0 };
+        let fy = if self.max.1 > self.min.1 { rng.gen_range(self.min.1..self.max.1) } else { self.min.1 };
+        Vec2::new(-world.map_width/2. + fx * world.map_width, -world.map_height/2. + fy * world.map_height)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let corners = [self.min.0, self.min.1, self.max.0, self.max.1];
+        if corners.iter().any(|f| !(0.0..=1.0).contains(f)) {
+            return Err(format!("homebase_region {:?}..{:?} must be inside 0..1", self.min, self.max));
+        }
+        if self.min.0 > self.max.0 || self.min.1 > self.max.1 {
+            return Err(format!("homebase_region has its min {:?} above its max {:?}", self.min, self.max));
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RaceConfig {
+    pub name: String,
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub texture: Option<String>,
+    #[serde(default)]
+    pub homebase_texture: Option<String>,
+    pub starting_population: (u32, u32),
+    pub homebase_region: HomebaseRegion,
+    pub aggressiveness: TraitRange,
+    pub avoidance: TraitRange,
+    #[serde(default)]
+    pub engineering: TraitRange,
+    #[serde(default)]
+    pub tribalism: TraitRange,
+    #[serde(default)]
+    pub reproducibility: TraitRange,
+    #[serde(default)]
+    pub fighting_score: TraitRange,
+    #[serde(default)]
+    pub perception: TraitRange, // times `movement.perception_radius`
+    #[serde(default)]
+    pub attack: TraitRange,
+    #[serde(default)]
+    pub defense: TraitRange,
+    #[serde(default = "TraitRange::lifespan")]
+    pub lifespan: TraitRange, // simulated seconds
+}
+
+impl RaceConfig {
+    pub fn color(&self) -> Color {
+        Color::rgb(self.color.0, self.color.1, self.color.2)
+    }
+
+    /// Ranges the world generation can draw from
+    pub fn validate(&self) -> Result<(), String> {
+        if self.starting_population.0 > self.starting_population.1 {
+            return Err(format!("starting_population has its min {} above its max {}", self.starting_population.0, self.starting_population.1));
+        }
+        self.homebase_region.validate()?;
+        let traits = [
+            ("aggressiveness", self.aggressiveness),
+            ("avoidance", self.avoidance),
+            ("engineering", self.engineering),
+            ("tribalism", self.tribalism),
+            ("reproducibility", self.reproducibility),
+            ("fighting_score", self.fighting_score),
+            ("perception", self.perception),
+            ("attack", self.attack),
+            ("defense", self.defense),
+            ("lifespan", self.lifespan),
+        ];
+        for (name, range) in traits {
+            range.validate(name)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Every race in the simulation, read from the races config file
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct Races {
+    pub list: Vec<RaceConfig>,
+}
+
+impl Races {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let races: Races = ron::from_str(&text).map_err(|e| format!("could not parse {}: {}", path, e))?;
+        if races.list.is_empty() {
+            return Err(format!("{} does not define any races", path));
+        }
+        for race in races.list.iter() {
+            race.validate().map_err(|e| format!("{}: race {}: {}", path, race.name, e))?;
+        }
+        Ok(races)
+    }
+
+    pub fn get(&self, race: SpeciesRace) -> &RaceConfig {
+        &self.list[race.0]
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.list.iter().map(|r| r.name.clone()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SpeciesRace, &RaceConfig)> {
+        self.list.iter().enumerate().map(|(i, r)| (SpeciesRace(i), r))
+    }
+}
+
+
+/// Sprites for every race, loaded once so spawning a species doesn't load its texture again.
+/// Races without a texture share a generated white sprite that is tinted with the race color
+#[derive(Resource, Default)]
+pub struct RaceAssets {
+    pub species: Vec<(Handle<Image>, Color)>,
+    pub homebases: Vec<(Handle<Image>, Color)>,
+}
+
+impl RaceAssets {
+    pub fn species_sprite(&self, race: SpeciesRace, transform: Transform) -> SpriteBundle {
+        let (texture, color) = self.species[race.0].clone();
+        SpriteBundle {
+            texture,
+            sprite: Sprite { color, ..default() },
+            transform,
+            ..default()
+        }
+    }
+
+    pub fn homebase_sprite(&self, race: SpeciesRace, transform: Transform) -> SpriteBundle {
+        let (texture, color) = self.homebases[race.0].clone();
+        SpriteBundle {
+            texture,
+            sprite: Sprite { color, ..default() },
+            transform,
+            ..default()
+        }
+    }
+}
+
+
+
+pub fn load_race_assets(
+    races: Res<Races>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut race_assets: ResMut<RaceAssets>,
+) {
+    let generated_species = images.add(generate_species_image());
+    let generated_homebase = images.add(generate_homebase_image());
+
+    for race in races.list.iter() {
+        let species = match &race.texture {
+            Some(path) => (asset_server.load(path.clone()), Color::WHITE),
+            None => (generated_species.clone(), race.color()),
+        };
+        let homebase = match &race.homebase_texture {
+            Some(path) => (asset_server.load(path.clone()), Color::WHITE),
+            None => (generated_homebase.clone(), race.color().with_a(0.6)),
+        };
+        race_assets.species.push(species);
+        race_assets.homebases.push(homebase);
+    }
+}
+
+
+// white triangle pointing up, the direction the species sprites face when they move
+fn generate_species_image() -> Image {
+    let size = GENERATED_SPRITE_SIZE;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        // image rows go from the top down, so the tip is at row 0
+        let half_width = (y as f32 + 1.) / 2.;
+        for x in 0..size {
+            let dx = (x as f32 + 0.5 - size as f32 / 2.).abs();
+            let alpha = if dx <= half_width { 255 } else { 0 };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+    Image::new(
+        Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+
+// white square the same size as the homebase textures
+fn generate_homebase_image() -> Image {
+    Image::new_fill(
+        Extent3d { width: 100, height: 100, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        &[255, 255, 255, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
@@ -0,0 +1,568 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use bevy::time::Virtual;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::clock::*;
+use crate::config::*;
+use crate::debug_ui::UiState;
+use crate::food_desire::FoodDesire;
+use crate::race::Races;
+use crate::snapshot::*;
+use crate::species::Species;
+use crate::statistics::Statistics;
+use crate::water_desire::WaterDesire;
+use crate::weather::WeatherConfig;
+
+/// Bump this whenever the layout of `ReplayFile` or `SimInput` changes
+pub const REPLAY_VERSION: u32 = 16;
+const REPLAY_FILE: &str = "replay.ron";
+const CHECKPOINT_INTERVAL_SEC: f32 = 30.;
+const MAX_CHECKPOINTS: usize = 20;
+const FAST_FORWARD_SPEED: f32 = 20.;
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    pub enabled: bool, // record the inputs and take checkpoints. Batch runs only record when the sweep asks for it
+    pub checkpoint_interval_sec: f32, // simulated seconds between the in-memory checkpoints the timeline jumps to
+    pub max_checkpoints: usize, // older checkpoints are thinned out beyond this many, the start is always kept
+    pub fast_forward_speed: f32,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            checkpoint_interval_sec: CHECKPOINT_INTERVAL_SEC,
+            max_checkpoints: MAX_CHECKPOINTS,
+            fast_forward_speed: FAST_FORWARD_SPEED,
+        }
+    }
+}
+
+
+/// The debug menu values the simulation reads
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SteeringInput {
+    pub steering_strength: f32,
+    pub max_velocity: f32,
+    pub max_acceleration: f32,
+    pub avoid_other_strength: f32,
+    pub avoid_same_strength: f32,
+}
+
+impl SteeringInput {
+    pub fn from_ui(ui_state: &UiState) -> Self {
+        Self {
+            steering_strength: ui_state.steering_strength,
+            max_velocity: ui_state.max_velocity,
+            max_acceleration: ui_state.max_acceleration,
+            avoid_other_strength: ui_state.avoid_other_strength,
+            avoid_same_strength: ui_state.avoid_same_strength,
+        }
+    }
+
+    pub fn apply(&self, ui_state: &mut UiState) {
+        ui_state.steering_strength = self.steering_strength;
+        ui_state.max_velocity = self.max_velocity;
+        ui_state.max_acceleration = self.max_acceleration;
+        ui_state.avoid_other_strength = self.avoid_other_strength;
+        ui_state.avoid_same_strength = self.avoid_same_strength;
+    }
+}
+
+
+/// Anything from outside the simulation that changes how it goes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SimInput {
+    Steering(SteeringInput),
+    Weather(WeatherConfig),
+    Config(SimConfig),
+    DespawnAll,
+}
+
+
+/// Inputs waiting for the start of the next simulation tick
+#[derive(Resource, Default)]
+pub struct PendingInputs(pub Vec<SimInput>);
+
+impl PendingInputs {
+    pub fn push(&mut self, input: SimInput) {
+        // a held key sends the same input every frame
+        if self.0.last() != Some(&input) {
+            self.0.push(input);
+        }
+    }
+}
+
+
+#[derive(Event)]
+pub struct SaveReplay(pub PathBuf);
+
+/// Jump to this tick of the recording
+#[derive(Event, Copy, Clone)]
+pub struct ScrubTo(pub u64);
+
+
+/// A recorded run: the world it started from and every input, by the tick it was applied at
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub version: u32,
+    pub tick_hz: f64,
+    pub races: Vec<String>,
+    pub seed: u64,
+    pub config: SimConfig,
+    pub start: Option<WorldSnapshot>, // None when the world was generated from the seed
+    pub inputs: Vec<(u64, SimInput)>,
+    pub end_tick: u64,
+}
+
+
+// read first, so an old recording gets a clear message instead of a parse error
+#[derive(Deserialize)]
+struct ReplayVersion {
+    version: u32,
+}
+
+
+impl ReplayFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        let header: ReplayVersion = ron::from_str(&text).map_err(|e| format!("{:?} is not a replay: {}", path, e))?;
+        if header.version != REPLAY_VERSION {
+            return Err(format!("{:?} is a version {} replay, this build reads version {}", path, header.version, REPLAY_VERSION));
+        }
+        let file: ReplayFile = ron::from_str(&text).map_err(|e| format!("could not parse {:?}: {}", path, e))?;
+        if file.tick_hz != SIM_TICK_HZ {
+            return Err(format!("{:?} was recorded at {} ticks per second, this build runs at {}", path, file.tick_hz, SIM_TICK_HZ));
+        }
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("could not create {:?}: {}", dir, e))?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("could not serialize the replay: {}", e))?;
+        fs::write(path, text).map_err(|e| format!("could not write {:?}: {}", path, e))
+    }
+
+    pub fn check_races(&self, races: &Races) -> Result<(), String> {
+        if races.names() != self.races {
+            return Err(format!("the replay was recorded with the races {:?}, but the races file has {:?}", self.races, races.names()));
+        }
+        Ok(())
+    }
+}
+
+
+pub fn default_replay_path(world: &WorldConfig) -> PathBuf {
+    PathBuf::from(&world.output_dir).join(REPLAY_FILE)
+}
+
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReplayMode {
+    Recording,
+    Playback,
+}
+
+
+/// The recording of the current run. While playing back, the recorded inputs are applied instead of the live ones
+/// until the end of the recording, after that it records again
+#[derive(Resource)]
+pub struct Replay {
+    pub mode: ReplayMode,
+    pub seed: u64,
+    pub config: SimConfig,
+    pub start: Option<WorldSnapshot>,
+    pub restore_start: bool, // put `start` back before the first tick
+    pub inputs: Vec<(u64, SimInput)>,
+    pub cursor: usize, // next input to play back
+    pub end_tick: u64,
+    pub checkpoints: BTreeMap<u64, WorldSnapshot>,
+    pub hold_at: Option<u64>, // stop the simulation at this tick
+    pub scrub_sec: f32,
+    last_steering: Option<SteeringInput>,
+    last_weather: Option<WeatherConfig>,
+}
+
+impl Replay {
+    pub fn record(seed: u64, config: SimConfig) -> Self {
+        Self {
+            mode: ReplayMode::Recording,
+            seed,
+            config,
+            start: None,
+            restore_start: false,
+            inputs: Vec::new(),
+            cursor: 0,
+            end_tick: 0,
+            checkpoints: BTreeMap::new(),
+            hold_at: None,
+            scrub_sec: 0.,
+            last_steering: None,
+            last_weather: None,
+        }
+    }
+
+    pub fn playback(file: ReplayFile) -> Self {
+        let mut replay = Self::record(file.seed, file.config);
+        replay.mode = ReplayMode::Playback;
+        replay.inputs = file.inputs;
+        replay.end_tick = file.end_tick;
+        if let Some(start) = file.start {
+            replay.checkpoints.insert(start.clock.frame, start.clone());
+            replay.start = Some(start);
+            replay.restore_start = true;
+        }
+        replay
+    }
+
+    /// Start a new recording from a loaded snapshot
+    pub fn restart_from(&mut self, snapshot: WorldSnapshot) {
+        *self = Self::record(snapshot.rng.seed, snapshot.config.clone());
+        self.checkpoints.insert(snapshot.clock.frame, snapshot.clone());
+        self.start = Some(snapshot);
+    }
+
+    pub fn to_file(&self, races: &Races, end_tick: u64) -> ReplayFile {
+        ReplayFile {
+            version: REPLAY_VERSION,
+            tick_hz: SIM_TICK_HZ,
+            races: races.names(),
+            seed: self.seed,
+            config: self.config.clone(),
+            start: self.start.clone(),
+            inputs: self.inputs.clone(),
+            end_tick,
+        }
+    }
+
+    pub fn start_tick(&self) -> u64 {
+        self.start.as_ref().map_or(0, |s| s.clock.frame)
+    }
+
+    /// The last tick that has been simulated or is in the recording
+    pub fn end_tick(&self, tick: u64) -> u64 {
+        match self.mode {
+            ReplayMode::Recording => tick,
+            ReplayMode::Playback => self.end_tick.max(tick),
+        }
+    }
+
+    /// Drop checkpoints until at most `max` are left. The one closest to the checkpoint before it goes first, the
+    /// oldest of those on a tie, so the recent past keeps its detail and the gaps grow further back
+    fn thin_checkpoints(&mut self, max: usize) {
+        while self.checkpoints.len() > max.max(2) {
+            let ticks: Vec<u64> = self.checkpoints.keys().copied().collect();
+            // never the first, the start of the recording, or the newest
+            let drop = (1..ticks.len() - 1)
+                .min_by_key(|&i| (ticks[i + 1] - ticks[i - 1], ticks[i]))
+                .map(|i| ticks[i]);
+            let Some(drop) = drop else { break; };
+            self.checkpoints.remove(&drop);
+        }
+    }
+
+    pub fn holding(&self, tick: u64) -> bool {
+        self.hold_at.map_or(false, |t| tick >= t)
+    }
+
+    // keep track of the values the debug menu has to show while playing back
+    fn remember(&mut self, input: &SimInput, ui_state: &UiState, weather_config: &WeatherConfig) {
+        match input {
+            SimInput::Steering(steering) => self.last_steering = Some(steering.clone()),
+            SimInput::Weather(weather) => self.last_weather = Some(weather.clone()),
+            SimInput::Config(_) => {
+                self.last_steering = Some(SteeringInput::from_ui(ui_state));
+                self.last_weather = Some(weather_config.clone());
+            },
+            SimInput::DespawnAll => {},
+        }
+    }
+}
+
+
+
+/// Run condition of `SimSet`, stops the simulation at the tick the timeline jumped to
+pub fn sim_should_step(
+    replay: Res<Replay>,
+    clock: Res<SimClock>,
+) -> bool {
+    !replay.holding(clock.frame)
+}
+
+
+/// Run condition of the world generation. Not needed when the world comes from a snapshot
+pub fn fresh_world(
+    resume_from: Option<Res<ResumeFrom>>,
+    replay: Res<Replay>,
+) -> bool {
+    resume_from.is_none() && replay.start.is_none()
+}
+
+
+
+/// Everything an input can change
+#[derive(SystemParam)]
+pub struct InputTargets<'w, 's> {
+    commands: Commands<'w, 's>,
+    config: Res<'w, SimConfig>,
+    ui_state: ResMut<'w, UiState>,
+    weather_config: ResMut<'w, WeatherConfig>,
+    stats: Option<ResMut<'w, Statistics>>,
+    species_query: Query<'w, 's, (&'static mut Species, &'static mut FoodDesire, &'static mut WaterDesire)>,
+    species_entities: Query<'w, 's, Entity, With<Species>>,
+}
+
+impl<'w, 's> InputTargets<'w, 's> {
+    fn apply(&mut self, input: &SimInput) {
+        match input {
+            SimInput::Steering(steering) => steering.apply(&mut self.ui_state),
+            SimInput::Weather(weather) => *self.weather_config = weather.clone(),
+            SimInput::Config(config) => {
+                // the weather sliders follow the file only when it changed the weather
+                if config.weather != self.config.weather {
+                    *self.weather_config = config.weather.clone();
+                }
+                apply_config_change(
+                    &self.config,
+                    config,
+                    &mut self.commands,
+                    &mut self.ui_state,
+                    self.stats.as_deref_mut(),
+                    &mut self.species_query,
+                );
+            },
+            SimInput::DespawnAll => {
+                for e in self.species_entities.iter() {
+                    self.commands.entity(e).despawn();
+                }
+            },
+        }
+    }
+}
+
+
+
+/// Take an in-memory checkpoint every `checkpoint_interval_sec`, for the timeline to jump back to.
+/// Runs first in the tick, before that tick's inputs are applied
+pub fn capture_replay_checkpoint(
+    mut replay: ResMut<Replay>,
+    source: SnapshotSource,
+    config: Res<ReplayConfig>,
+) {
+    if !config.enabled { return; }
+    let tick = source.clock.frame;
+    let interval = ((config.checkpoint_interval_sec as f64 * SIM_TICK_HZ).round() as u64).max(1);
+    if tick % interval != 0 || replay.checkpoints.contains_key(&tick) { return; }
+    replay.checkpoints.insert(tick, source.capture());
+    replay.thin_checkpoints(config.max_checkpoints);
+}
+
+
+
+/// Record the inputs of this tick, or apply the recorded ones when playing back. With recording off the inputs are
+/// only applied
+pub fn apply_replay_inputs(
+    mut replay: ResMut<Replay>,
+    mut pending: ResMut<PendingInputs>,
+    clock: Res<SimClock>,
+    config: Res<ReplayConfig>,
+    mut targets: InputTargets,
+) {
+    let tick = clock.frame;
+    if replay.mode == ReplayMode::Playback && tick >= replay.end_tick {
+        replay.mode = ReplayMode::Recording;
+        info!("End of the recording at {:.1}s, recording again", clock.elapsed);
+    }
+
+    match replay.mode {
+        ReplayMode::Recording => {
+            // the debug menu changes its values between ticks, they are recorded at the tick they first apply to
+            let steering = SteeringInput::from_ui(&targets.ui_state);
+            if replay.last_steering.as_ref() != Some(&steering) {
+                if config.enabled { replay.inputs.push((tick, SimInput::Steering(steering.clone()))); }
+                replay.last_steering = Some(steering);
+            }
+            if replay.last_weather.as_ref() != Some(&*targets.weather_config) {
+                let weather = targets.weather_config.clone();
+                if config.enabled { replay.inputs.push((tick, SimInput::Weather(weather.clone()))); }
+                replay.last_weather = Some(weather);
+            }
+
+            for input in pending.0.drain(..) {
+                targets.apply(&input);
+                replay.remember(&input, &targets.ui_state, &targets.weather_config);
+                if config.enabled { replay.inputs.push((tick, input)); }
+            }
+        },
+        ReplayMode::Playback => {
+            if !pending.0.is_empty() {
+                warn!("Ignoring input while playing back a recording");
+                pending.0.clear();
+            }
+
+            while let Some((input_tick, input)) = replay.inputs.get(replay.cursor).cloned() {
+                if input_tick > tick { break; }
+                targets.apply(&input);
+                replay.remember(&input, &targets.ui_state, &targets.weather_config);
+                replay.cursor += 1;
+            }
+
+            // undo whatever was changed in the debug menu since the last tick
+            if let Some(steering) = &replay.last_steering {
+                steering.apply(&mut targets.ui_state);
+            }
+            if let Some(weather) = &replay.last_weather {
+                *targets.weather_config = weather.clone();
+            }
+        },
+    }
+}
+
+
+
+/// Jump to a tick: go back to the last checkpoint before it and simulate forward at `fast_forward_speed`,
+/// playing back the recorded inputs. Jumping back while recording turns the rest of the recording into a playback
+pub fn scrub_replay(
+    mut events: EventReader<ScrubTo>,
+    mut replay: ResMut<Replay>,
+    mut restore: SnapshotRestore,
+    clock: Res<SimClock>,
+    config: Res<ReplayConfig>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    // a recording that started from a snapshot puts that world back first
+    if replay.restore_start {
+        replay.restore_start = false;
+        if let Some(start) = replay.start.clone() {
+            restore.restore(&start);
+        }
+    }
+
+    let Some(ScrubTo(target)) = events.read().last().copied() else { return; };
+    let now = clock.frame;
+    let target = target.min(replay.end_tick(now));
+    let Some(checkpoint) = replay.checkpoints.range(..=target).next_back().map(|(_, c)| c.clone()) else {
+        warn!("There is no checkpoint before {:.1}s", target as f64 / SIM_TICK_HZ);
+        return;
+    };
+
+    if replay.mode == ReplayMode::Recording {
+        replay.mode = ReplayMode::Playback;
+        replay.end_tick = now;
+    }
+
+    // going forward from here is quicker than going back to a checkpoint
+    let checkpoint_tick = checkpoint.clock.frame;
+    if target < now || checkpoint_tick > now {
+        restore.restore(&checkpoint);
+        replay.cursor = replay.inputs.partition_point(|(t, _)| *t < checkpoint_tick);
+        replay.last_steering = Some(SteeringInput::from_ui(&checkpoint.ui));
+        replay.last_weather = Some(checkpoint.config.weather.clone());
+    }
+
+    info!("Jumping to {:.1}s from the checkpoint at {:.1}s", target as f64 / SIM_TICK_HZ, checkpoint.clock.elapsed);
+    replay.hold_at = Some(target);
+    time.set_relative_speed(config.fast_forward_speed);
+    time.unpause();
+}
+
+
+
+/// Pause once a jump reached its tick, so whatever happened there can be inspected
+pub fn finish_scrub(
+    replay: Res<Replay>,
+    clock: Res<SimClock>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if replay.holding(clock.frame) && !time.is_paused() {
+        time.pause();
+        time.set_relative_speed(1.);
+        info!("Stopped at {:.1}s", clock.elapsed);
+    }
+}
+
+
+
+pub fn save_replay(
+    mut events: EventReader<SaveReplay>,
+    replay: Res<Replay>,
+    races: Res<Races>,
+    clock: Res<SimClock>,
+    config: Res<ReplayConfig>,
+) {
+    for event in events.read() {
+        if !config.enabled {
+            warn!("Not saving the replay {:?}, recording is off (replay.enabled)", event.0);
+            continue;
+        }
+        let file = replay.to_file(&races, replay.end_tick(clock.frame));
+        match file.save(&event.0) {
+            Ok(()) => info!("Saved replay {:?}, {} inputs", event.0, file.inputs.len()),
+            Err(e) => warn!("Could not save the replay: {}", e),
+        }
+    }
+}
+
+
+
+/// F6 saves the recording to `<output_dir>/replay.ron`
+pub fn replay_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    world: Res<WorldConfig>,
+    mut save: EventWriter<SaveReplay>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        save.send(SaveReplay(default_replay_path(&world)));
+    }
+}
+
+
+
+pub fn replay_timeline_ui(
+    mut contexts: EguiContexts,
+    mut replay: ResMut<Replay>,
+    clock: Res<SimClock>,
+    world: Res<WorldConfig>,
+    mut time: ResMut<Time<Virtual>>,
+    mut scrub: EventWriter<ScrubTo>,
+    mut save: EventWriter<SaveReplay>,
+) {
+    let start_sec = (replay.start_tick() as f64 / SIM_TICK_HZ) as f32;
+    let end_sec = (replay.end_tick(clock.frame) as f64 / SIM_TICK_HZ) as f32;
+    let mode = match replay.mode {
+        ReplayMode::Recording => "Recording",
+        ReplayMode::Playback => "Playback",
+    };
+
+    egui::TopBottomPanel::bottom("timeline").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} {:.1}s / {:.1}s", mode, clock.elapsed, end_sec));
+            ui.add(egui::Slider::new(&mut replay.scrub_sec, start_sec..=end_sec.max(start_sec)).text("s"));
+            if ui.button("Jump").clicked() {
+                scrub.send(ScrubTo((replay.scrub_sec as f64 * SIM_TICK_HZ).round() as u64));
+            }
+            if time.is_paused() {
+                if ui.button("Play").clicked() {
+                    replay.hold_at = None;
+                    time.unpause();
+                }
+            } else if ui.button("Pause").clicked() {
+                time.pause();
+            }
+            if ui.button("Save replay (F6)").clicked() {
+                save.send(SaveReplay(default_replay_path(&world)));
+            }
+        });
+    });
+}
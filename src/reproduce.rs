@@ -1,16 +1,45 @@
 use bevy::{prelude::*, time::Stopwatch};
-use crate::{health::*, water_desire::*, food_desire::*, species::*};
+use crate::{health::*, water_desire::*, food_desire::*, species::*, fight::Fight, navigation::NavPath, behavior::Steering, race::RaceAssets, sim_rng::SimRng, lineage::Lineages};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 const MIN_REPRODUCE_THRESHOLD: f32 = 0.95;
 const REPRODUCE_GRACE_PERIOD_SEC: f32 = 10.0;
+const MUTATION: f32 = 0.05;
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReproductionConfig {
+    pub min_reproduce_threshold: f32,
+    pub grace_period_sec: f32,
+    pub mutation: f32, // an inherited trait is the parent's times a random factor between 1 - mutation and 1 + mutation
+}
+
+impl Default for ReproductionConfig {
+    fn default() -> Self {
+        Self {
+            min_reproduce_threshold: MIN_REPRODUCE_THRESHOLD,
+            grace_period_sec: REPRODUCE_GRACE_PERIOD_SEC,
+            mutation: MUTATION,
+        }
+    }
+}
+
+impl ReproductionConfig {
+    /// A child's value of a trait it inherits from its parent
+    pub fn mutate(&self, value: f32, rng: &mut impl Rng) -> f32 {
+        if self.mutation <= 0. { return value; }
+        value * rng.gen_range(1. - self.mutation..=1. + self.mutation)
+    }
+}
 
 
 #[derive(Event)]
 pub struct Reproduce(pub Entity);
 
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Reproduction {
     pub time_since: Stopwatch,
     pub genes: Vec<f32>,
@@ -34,7 +63,14 @@ impl Reproduction {
 pub fn react_to_reproduction_event(
     mut reproduce_event: EventReader<Reproduce>,
     mut query: Query<(&mut Species, &mut Reproduction)>,
-    asset_server: Res<AssetServer>,
+    fights: Query<&Fight>,
+    mut hungers: Query<&mut FoodDesire>,
+    metabolism: Res<MetabolismConfig>,
+    race_assets: Res<RaceAssets>,
+    configs: SpeciesConfigs,
+    reproduction_config: Res<ReproductionConfig>,
+    mut rng: ResMut<SimRng>,
+    mut lineages: ResMut<Lineages>,
     mut commands: Commands,
 ){
     for reproduce_event in reproduce_event.read() {
@@ -47,37 +83,41 @@ pub fn react_to_reproduction_event(
         match query.get_component_mut::<Species>(e) {
             Ok(mut this_species) => {
                 this_species.need_to_reproduce = false;
-                let new_race = &this_species.race;
-                let texture: Handle<Image>; 
-                // TODO instead of loading the species texture again, have it stored somewhere??
-                match &new_race {
-                    SpeciesRace::Red => {texture = asset_server.load("textures/species/red_species.png")},
-                    SpeciesRace::Blue => {texture = asset_server.load("textures/species/blue_species.png")},
-                    SpeciesRace::Yellow => {texture = asset_server.load("textures/species/yellow_species.png")},
-                    SpeciesRace::Green => {texture = asset_server.load("textures/species/green_species.png")},
-                }
                 // birth a new member of this type of species at the parent's location
-                // TODO make baby species smaller in size, grow over time?
                 let parent_pos = this_species.position;
+                let mut child = Species::new(
+                    parent_pos, 
+                    this_species.race, 
+                    this_species.homebase, 
+                    reproduction_config.mutate(this_species.aggressiveness, &mut *rng), 
+                    reproduction_config.mutate(this_species.avoidance, &mut *rng));
+                child.engineering = reproduction_config.mutate(this_species.engineering, &mut *rng);
+                child.tribalism = reproduction_config.mutate(this_species.tribalism, &mut *rng);
+                child.reproducibility = reproduction_config.mutate(this_species.reproducibility, &mut *rng);
+                child.fighting_score = reproduction_config.mutate(this_species.fighting_score, &mut *rng);
+                child.perception_radius = reproduction_config.mutate(this_species.perception_radius, &mut *rng);
+                child.lifespan = reproduction_config.mutate(this_species.lifespan, &mut *rng);
+                child.generation = this_species.generation + 1;
+                child.lineage = this_species.lineage;
+                lineages.born(&child);
+                let fight = fights.get(e).map_or(Fight::default(), |f| f.inherit(&reproduction_config, &mut *rng));
+                if let Ok(mut hunger) = hungers.get_mut(e) {
+                    hunger.val -= metabolism.reproduction_cost;
+                }
                 commands.spawn(
-                    (SpriteBundle {
-                        texture,
-                        transform: Transform {
-                            translation: parent_pos,
-                            scale: Vec3::splat(SPECIES_TEXTURE_SCALE),
-                            rotation: Quat::default()
-                        },
-                        ..default()},
-                    Species::new(
-                        parent_pos, 
-                        this_species.race, 
-                        this_species.homebase, 
-                        this_species.aggressiveness, 
-                        this_species.avoidance),
-                    Health::default(),
-                    WaterDesire::default(),
-                    FoodDesire::default(),
+                    (race_assets.species_sprite(this_species.race, Transform {
+                        translation: parent_pos,
+                        scale: Vec3::splat(SPECIES_TEXTURE_SCALE * configs.aging.juvenile_scale),
+                        rotation: Quat::default()
+                    }),
+                    child,
+                    Health::full(&configs.health),
+                    WaterDesire::new(&configs.thirst),
+                    FoodDesire::new(&configs.hunger),
+                    fight,
                     Reproduction::default(),
+                    NavPath::default(),
+                    Steering::default(),
                     ),
                 );
             },
@@ -98,6 +138,9 @@ pub fn react_to_reproduction_event(
 
 pub fn update_reproduction(
     mut query: Query<(Entity, &mut Reproduction, &mut Species, &FoodDesire, &WaterDesire, &Health)>,
+    configs: SpeciesConfigs,
+    reproduction_config: Res<ReproductionConfig>,
+    mut rng: ResMut<SimRng>,
     time: Res<Time>,
     mut reproduce_event_sender: EventWriter<Reproduce>,
     
@@ -106,22 +149,22 @@ pub fn update_reproduction(
 
         // tick time since reproduce stopwatch
         reproduction.time_since.tick(time.delta());
+        if sp.stage(&configs.aging) == LifeStage::Juvenile { continue; }
 
 
         // TODO reproduce if stats are over some threshold? Or reproduce on random chance weighted according to good
         // stats? Genetic algorithm?
-        let mut rng = rand::thread_rng();
-        let threshold = rng.gen_range(MIN_REPRODUCE_THRESHOLD..1.0);
+        let threshold = rng.gen_range(reproduction_config.min_reproduce_threshold..1.0);
         let mut score = 0.0;
         //info!("Health: {}, Water: {}, Food: {}", health.val, water_des.val, food_des.val);
         if health.val > 0. { score += health.val};
         if water_des.val > 0. { score += water_des.val };
         if food_des.val > 0. { score += food_des.val };
-        score /= MAX_HEALTH + MAX_WATER + MAX_HUNGER;
+        score /= configs.health.max_health + configs.thirst.max_water + configs.hunger.max_hunger;
         //info!("Score: {}", score);
 
         // trigger reproduction event
-        if score > threshold && reproduction.time_since.elapsed_secs() > REPRODUCE_GRACE_PERIOD_SEC {
+        if score > threshold && reproduction.time_since.elapsed_secs() > reproduction_config.grace_period_sec {
             reproduce_event_sender.send(Reproduce(e));
         }
 
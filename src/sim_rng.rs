@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+
+/// The one random number generator of the simulation. It is seeded, so a run can be repeated, and its state is
+/// saved in snapshots, so a resumed run draws the same numbers it would have drawn without stopping
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct SimRng {
+    pub seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Seeded from the config, or a random seed when the config doesn't set one
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        Self::new(seed.unwrap_or_else(rand::random))
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,337 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::autosave::*;
+use crate::behavior::Steering;
+use crate::clock::SimClock;
+use crate::config::*;
+use crate::debug_ui::UiState;
+use crate::fight::Fight;
+use crate::food_desire::FoodDesire;
+use crate::food_source::*;
+use crate::health::*;
+use crate::homebase::*;
+use crate::engineering::*;
+use crate::lineage::Lineages;
+use crate::navigation::*;
+use crate::obstacle::*;
+use crate::race::*;
+use crate::replay::Replay;
+use crate::reproduce::Reproduction;
+use crate::sim_rng::SimRng;
+use crate::species::Species;
+use crate::water_desire::WaterDesire;
+use crate::water_source::*;
+use crate::weather::*;
+
+/// Bump this whenever the layout of `WorldSnapshot` or any of the components in it changes
+pub const SNAPSHOT_VERSION: u32 = 16;
+const SNAPSHOT_FILE: &str = "snapshot.ron";
+
+
+/// The snapshot this run was started from with `--load <path>`. The world is not generated when it is present
+#[derive(Resource, Clone, Debug)]
+pub struct ResumeFrom(pub PathBuf);
+
+
+#[derive(Event)]
+pub struct SaveSnapshot(pub PathBuf);
+
+#[derive(Event)]
+pub struct LoadSnapshot(pub PathBuf);
+
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpeciesSnapshot {
+    pub transform: Transform,
+    pub species: Species,
+    pub health: Health,
+    pub food_desire: FoodDesire,
+    pub water_desire: WaterDesire,
+    pub fight: Fight,
+    pub reproduction: Reproduction,
+    pub nav_path: NavPath,
+}
+
+
+/// Everything needed to continue a run exactly where it was saved. The flow fields are rebuilt after loading,
+/// they only depend on the navigation grid. Blood splats are left out
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldSnapshot {
+    pub version: u32,
+    pub races: Vec<String>,
+    pub config: SimConfig,
+    pub clock: SimClock,
+    pub rng: SimRng,
+    pub weather: Weather,
+    pub ui: UiState,
+    pub food_locations: FoodLocations,
+    pub nav_grid: NavGrid,
+    pub lineages: Lineages,
+    pub homebases: Vec<Homebase>,
+    pub water_sources: Vec<WaterSource>,
+    pub food_sources: Vec<FoodSource>,
+    pub obstacles: Vec<Vec3>, // rocks, the structures are saved with their state
+    pub structures: Vec<Structure>,
+    pub resource_nodes: Vec<ResourceNode>,
+    pub species: Vec<SpeciesSnapshot>,
+}
+
+
+// read first, so an old snapshot gets a clear message instead of a parse error
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+
+impl WorldSnapshot {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read {:?}: {}", path, e))?;
+        let header: SnapshotVersion = ron::from_str(&text).map_err(|e| format!("{:?} is not a snapshot: {}", path, e))?;
+        if header.version != SNAPSHOT_VERSION {
+            return Err(format!("{:?} is a version {} snapshot, this build reads version {}", path, header.version, SNAPSHOT_VERSION));
+        }
+        ron::from_str(&text).map_err(|e| format!("could not parse {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("could not create {:?}: {}", dir, e))?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("could not serialize the snapshot: {}", e))?;
+        fs::write(path, text).map_err(|e| format!("could not write {:?}: {}", path, e))
+    }
+
+    /// A snapshot only fits a world with the same races in the same order
+    pub fn check_races(&self, races: &Races) -> Result<(), String> {
+        let names = races.names();
+        if names != self.races {
+            return Err(format!("the snapshot was saved with the races {:?}, but the races file has {:?}", self.races, names));
+        }
+        Ok(())
+    }
+}
+
+
+pub fn default_snapshot_path(world: &WorldConfig) -> PathBuf {
+    PathBuf::from(&world.output_dir).join(SNAPSHOT_FILE)
+}
+
+
+
+/// F5 saves the world to `<output_dir>/snapshot.ron`, F9 loads it again
+pub fn snapshot_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    world: Res<WorldConfig>,
+    mut save: EventWriter<SaveSnapshot>,
+    mut load: EventWriter<LoadSnapshot>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        save.send(SaveSnapshot(default_snapshot_path(&world)));
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        load.send(LoadSnapshot(default_snapshot_path(&world)));
+    }
+}
+
+
+
+/// Everything a snapshot is taken from
+#[derive(SystemParam)]
+pub struct SnapshotSource<'w, 's> {
+    species_query: Query<'w, 's, (&'static Transform, &'static Species, &'static Health, &'static FoodDesire, &'static WaterDesire, &'static Fight, &'static Reproduction, &'static NavPath)>,
+    home_query: Query<'w, 's, &'static Homebase>,
+    water_query: Query<'w, 's, &'static WaterSource>,
+    food_query: Query<'w, 's, &'static FoodSource>,
+    obstacle_query: Query<'w, 's, &'static Obstacle, Without<Structure>>,
+    structure_query: Query<'w, 's, &'static Structure>,
+    node_query: Query<'w, 's, &'static ResourceNode>,
+    pub config: Res<'w, SimConfig>,
+    weather_config: Res<'w, WeatherConfig>,
+    races: Res<'w, Races>,
+    pub clock: Res<'w, SimClock>,
+    rng: Res<'w, SimRng>,
+    weather: Res<'w, Weather>,
+    ui_state: Res<'w, UiState>,
+    food_locations: Res<'w, FoodLocations>,
+    nav_grid: Res<'w, NavGrid>,
+    lineages: Res<'w, Lineages>,
+}
+
+impl<'w, 's> SnapshotSource<'w, 's> {
+    pub fn capture(&self) -> WorldSnapshot {
+        let mut config = self.config.clone();
+        // the weather frequencies can be changed from the debug menu
+        config.weather = self.weather_config.clone();
+
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            races: self.races.names(),
+            config,
+            clock: self.clock.clone(),
+            rng: self.rng.clone(),
+            weather: self.weather.clone(),
+            ui: self.ui_state.clone(),
+            food_locations: self.food_locations.clone(),
+            nav_grid: self.nav_grid.clone(),
+            lineages: self.lineages.clone(),
+            homebases: self.home_query.iter().cloned().collect(),
+            water_sources: self.water_query.iter().cloned().collect(),
+            food_sources: self.food_query.iter().cloned().collect(),
+            obstacles: self.obstacle_query.iter().map(|o| o.position).collect(),
+            structures: self.structure_query.iter().cloned().collect(),
+            resource_nodes: self.node_query.iter().cloned().collect(),
+            species: self.species_query.iter().map(|(tf, sp, health, food_desire, water_desire, fight, reproduction, nav_path)| SpeciesSnapshot {
+                transform: *tf,
+                species: sp.clone(),
+                health: health.clone(),
+                food_desire: food_desire.clone(),
+                water_desire: water_desire.clone(),
+                fight: fight.clone(),
+                reproduction: reproduction.clone(),
+                nav_path: nav_path.clone(),
+            }).collect(),
+        }
+    }
+}
+
+
+
+/// Everything needed to put a snapshot back into the world
+#[derive(SystemParam)]
+pub struct SnapshotRestore<'w, 's> {
+    commands: Commands<'w, 's>,
+    existing: Query<'w, 's, Entity, Or<(With<Species>, With<FoodSource>, With<WaterSource>, With<Homebase>, With<Obstacle>, With<Blood>, With<ResourceNode>)>>,
+    pub races: Res<'w, Races>,
+    race_assets: Res<'w, RaceAssets>,
+    asset_server: Res<'w, AssetServer>,
+    world: Res<'w, WorldConfig>,
+}
+
+impl<'w, 's> SnapshotRestore<'w, 's> {
+    /// Replace the whole world with the one in the snapshot. Entities are spawned in the order they were saved in,
+    /// so queries visit them in the same order as before and the run continues exactly the same
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        for e in self.existing.iter() {
+            self.commands.entity(e).despawn();
+        }
+
+        for home in snapshot.homebases.iter() {
+            self.commands.spawn((home.sprite(&self.race_assets), home.clone()));
+        }
+
+        let water_handle: Handle<Image> = self.asset_server.load(WATER_TEXTURE);
+        for water_source in snapshot.water_sources.iter() {
+            self.commands.spawn((
+                SpriteBundle {
+                    texture: water_handle.clone(),
+                    transform: Transform {
+                        translation: water_source.position,
+                        rotation: Quat::default(),
+                        scale: Vec3::splat(water_source.sprite_scale()), // bigger during a flood
+                    },
+                    ..default()
+                },
+                water_source.clone(),
+            ));
+        }
+
+        let food_handle: Handle<Image> = self.asset_server.load(FOOD_TEXTURE);
+        for food_source in snapshot.food_sources.iter() {
+            self.commands.spawn((food_source.sprite(food_handle.clone()), food_source.clone()));
+        }
+
+        for position in snapshot.obstacles.iter() {
+            self.commands.spawn(rock_bundle(*position));
+        }
+
+        for structure in snapshot.structures.iter() {
+            self.commands.spawn((structure.bundle(self.races.get(structure.race).color()), structure.clone()));
+        }
+
+        for node in snapshot.resource_nodes.iter() {
+            self.commands.spawn((node.sprite(), node.clone()));
+        }
+
+        for saved in snapshot.species.iter() {
+            // spawned in one go, so they end up in the same archetype as before
+            self.commands.spawn((
+                self.race_assets.species_sprite(saved.species.race, saved.transform),
+                saved.species.clone(),
+                saved.health.clone(),
+                saved.food_desire.clone(),
+                saved.water_desire.clone(),
+                saved.fight.clone(),
+                saved.reproduction.clone(),
+                saved.nav_path.clone(),
+                Steering::default(),
+            ));
+        }
+
+        // the map and races are already built, so the world section of the running config is kept
+        let mut config = snapshot.config.clone();
+        if config.world != *self.world {
+            warn!("The snapshot was saved with a different world config, keeping the current one");
+            config.world = self.world.clone();
+        }
+        config.apply(&mut self.commands);
+
+        self.commands.insert_resource(snapshot.clock.clone());
+        self.commands.insert_resource(snapshot.rng.clone());
+        self.commands.insert_resource(snapshot.weather.clone());
+        self.commands.insert_resource(snapshot.ui.clone());
+        self.commands.insert_resource(snapshot.food_locations.clone());
+        self.commands.insert_resource(snapshot.nav_grid.clone());
+        self.commands.insert_resource(snapshot.lineages.clone());
+    }
+}
+
+
+
+/// A checkpoint that was written makes room by deleting the oldest ones. When the save fails they are all kept
+pub fn save_snapshot(
+    mut events: EventReader<SaveSnapshot>,
+    source: SnapshotSource,
+    autosave: Res<AutosaveConfig>,
+) {
+    for event in events.read() {
+        let snapshot = source.capture();
+        match snapshot.save(&event.0) {
+            Ok(()) => {
+                info!("Saved snapshot {:?} at {:.1}s, {} species", event.0, snapshot.clock.elapsed, snapshot.species.len());
+                let dir = checkpoint_dir(&source.config.world);
+                if event.0.parent() == Some(dir.as_path()) {
+                    prune_checkpoints(&dir, autosave.keep);
+                }
+            },
+            Err(e) => warn!("Could not save the snapshot: {}", e),
+        }
+    }
+}
+
+
+
+/// Load a snapshot from a file. A snapshot that can't be read leaves the world as it is. The replay recording
+/// starts over from the loaded world
+pub fn load_snapshot(
+    mut events: EventReader<LoadSnapshot>,
+    mut restore: SnapshotRestore,
+    mut replay: ResMut<Replay>,
+) {
+    let Some(event) = events.read().last() else { return; };
+    let snapshot = match WorldSnapshot::load(&event.0).and_then(|s| s.check_races(&restore.races).map(|_| s)) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            warn!("Could not load the snapshot: {}", e);
+            return;
+        },
+    };
+
+    restore.restore(&snapshot);
+    info!("Loaded snapshot {:?} at {:.1}s, {} species", event.0, snapshot.clock.elapsed, snapshot.species.len());
+    replay.restart_from(snapshot);
+}
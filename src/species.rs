@@ -1,34 +1,74 @@
 use rand::Rng;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use bevy::ecs::system::SystemParam;
 use noise::{NoiseFn, Perlin};
 use crate::food_desire::*;
 use crate::reproduce::Reproduction;
 use crate::water_desire::*;
 use crate::fight::*;
+use crate::navigation::NavPath;
+use crate::race::*;
+use crate::behavior::{MovementConfig, Steering};
+use crate::sim_rng::SimRng;
+use crate::lineage::Lineages;
 use bevy::math::f32::{Vec2, Vec3};
 
 use crate::health::*;
 use crate::homebase::*;
+use crate::engineering::Material;
 
 
 
 
 const SPAWN_SPREAD: f64 = 200.;
 pub const SPECIES_TEXTURE_SCALE: f32 = 2.0;
-const MIN_SPECIES_SPAWN: u32 = 10;
-const MAX_SPECIES_SPAWN: u32 = 50;
 const PERCEPTION_RADIUS: f32 = 100.;
+pub const LIFESPAN_SEC: f32 = 300.;
+const MATURITY_SEC: f32 = 30.;
+const ELDER_FRACTION: f32 = 0.8;
+const ELDER_DECLINE_RATE: f32 = 0.1;
+const JUVENILE_SCALE: f32 = 0.6;
+const JUVENILE_SPEED: f32 = 0.6;
 
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-pub enum SpeciesRace {
-    Red,
-    Blue,
-    Green,
-    Yellow,
+/// Species are juveniles until `maturity_sec` and elders for the last part of their lifespan. The lifespan itself
+/// is a trait of every species
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgingConfig {
+    pub maturity_sec: f32,
+    pub elder_fraction: f32, // of the lifespan, elders are older than this
+    pub elder_decline_rate: f32, // health lost per second as an elder
+    pub juvenile_scale: f32, // sprite size, relative to an adult
+    pub juvenile_speed: f32, // max velocity, relative to an adult
 }
 
-#[derive(Component)]
+impl Default for AgingConfig {
+    fn default() -> Self {
+        Self {
+            maturity_sec: MATURITY_SEC,
+            elder_fraction: ELDER_FRACTION,
+            elder_decline_rate: ELDER_DECLINE_RATE,
+            juvenile_scale: JUVENILE_SCALE,
+            juvenile_speed: JUVENILE_SPEED,
+        }
+    }
+}
+
+
+/// Juveniles can't reproduce or start fights and are smaller and slower, elders slowly lose health
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LifeStage {
+    Juvenile,
+    Adult,
+    Elder,
+}
+
+
+fn default_lifespan() -> f32 { LIFESPAN_SEC }
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Species {
     pub aggressiveness: f32,
     pub engineering: f32,
@@ -39,7 +79,7 @@ pub struct Species {
     pub need_to_reproduce: bool,
     pub race: SpeciesRace,
     pub target_pos: Vec3,
-    pub homebase: Vec3,
+    pub homebase: Option<Vec3>, // None for nomads, whose homebase was destroyed
     pub steering_forces: Vec3,
 
     pub n_neighbors: u32,
@@ -48,12 +88,33 @@ pub struct Species {
     pub acceleration: Vec3,
     pub velocity: Vec3,
     pub position: Vec3,
+
+    #[serde(default)]
+    pub age: f32, // simulated seconds
+    #[serde(default = "default_lifespan")]
+    pub lifespan: f32, // dies of old age at this age
+    #[serde(default)]
+    pub generation: u32, // 0 for the starting population
+    #[serde(default)]
+    pub lineage: u32, // index into `Lineages`
+    #[serde(default)]
+    pub carrying: Option<(Material, f32)>, // building material on the way home
+}
+
+
+/// The configs needed to spawn a new species with full health, food and water
+#[derive(SystemParam)]
+pub struct SpeciesConfigs<'w> {
+    pub health: Res<'w, HealthConfig>,
+    pub hunger: Res<'w, HungerConfig>,
+    pub thirst: Res<'w, ThirstConfig>,
+    pub movement: Res<'w, MovementConfig>,
+    pub aging: Res<'w, AgingConfig>,
 }
 
 
 impl Species {
-    pub fn new(position: Vec3, race: SpeciesRace, homebase: Vec3, aggressiveness: f32, avoidance: f32) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new(position: Vec3, race: SpeciesRace, homebase: Option<Vec3>, aggressiveness: f32, avoidance: f32) -> Self {
         // let x_vel = rng.gen_range(-10.0..10.0);
         // let y_vel = rng.gen_range(-10.0..10.0);
         Self {
@@ -61,7 +122,7 @@ impl Species {
             reproduction_factor: 0.0,
             acceleration: Vec3::ZERO,
             velocity: Vec3::ZERO,
-            target_pos: homebase,
+            target_pos: homebase.unwrap_or(position),
             position,
             perception_radius: 100.0,
             n_neighbors: 0,
@@ -75,6 +136,27 @@ impl Species {
             reproducibility: 1.0,
             fighting_score: 1.0,
             need_to_reproduce: false,
+
+            age: 0.,
+            lifespan: LIFESPAN_SEC,
+            generation: 0,
+            lineage: 0,
+            carrying: None,
+        }
+    }
+
+    /// Body size relative to an adult
+    pub fn size(&self, config: &AgingConfig) -> f32 {
+        if self.stage(config) == LifeStage::Juvenile { config.juvenile_scale } else { 1. }
+    }
+
+    pub fn stage(&self, config: &AgingConfig) -> LifeStage {
+        if self.age < config.maturity_sec {
+            LifeStage::Juvenile
+        } else if self.age >= self.lifespan * config.elder_fraction {
+            LifeStage::Elder
+        } else {
+            LifeStage::Adult
         }
     }
 }
@@ -83,7 +165,7 @@ impl Species {
 impl Default for Species {
     fn default() -> Self {
         Self {
-            race: SpeciesRace::Red,
+            race: SpeciesRace(0),
             reproduction_factor: 0.0,
             acceleration: Vec3::ZERO,
             velocity: Vec3::new(0., 0., 0.),
@@ -91,7 +173,7 @@ impl Default for Species {
             target_pos: Vec3::ZERO,
             perception_radius: PERCEPTION_RADIUS,
             n_neighbors: 0,
-            homebase: Vec3::ZERO,
+            homebase: Some(Vec3::ZERO),
             steering_forces: Vec3::ZERO,
             
             aggressiveness: 1.0,
@@ -101,6 +183,12 @@ impl Default for Species {
             reproducibility: 1.0,
             fighting_score: 1.0,
             need_to_reproduce: false,
+
+            age: 0.,
+            lifespan: LIFESPAN_SEC,
+            generation: 0,
+            lineage: 0,
+            carrying: None,
         }
     }
 
@@ -110,19 +198,16 @@ impl Default for Species {
 
 pub fn initial_species_group_spawn(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    races: Res<Races>,
+    race_assets: Res<RaceAssets>,
+    configs: SpeciesConfigs,
     mut home_query: Query<&mut Homebase>,
+    mut lineages: ResMut<Lineages>,
+    mut rng: ResMut<SimRng>,
 ){
     /* Generate a cluster of sprites as some center coordinate with random offsets from the center
      * with Perlin noise. 
      */
-    let blue_species_handle: Handle<Image> = asset_server.load("textures/species/blue_species.png");
-    let red_species_handle: Handle<Image>  = asset_server.load("textures/species/red_species.png");
-    let yellow_species_handle: Handle<Image> = asset_server.load("textures/species/yellow_species.png");
-    let green_species_handle: Handle<Image> = asset_server.load("textures/species/green_species.png");
-
-    let mut rng = rand::thread_rng();
-
     // generate a 2D perlin noise map
     let perlin_x: u32 = 50;
     let perlin_y: u32 = 50;
@@ -139,8 +224,10 @@ pub fn initial_species_group_spawn(
     for home in home_query.iter_mut() {
 
         let race = home.species_race;
+        let race_config = races.get(race);
 
-        let number_sprites = rng.gen_range(MIN_SPECIES_SPAWN..MAX_SPECIES_SPAWN);
+        let (min_spawn, max_spawn) = race_config.starting_population;
+        let number_sprites = rng.gen_range(min_spawn..=max_spawn.max(min_spawn));
         // let number_sprites = 2;
         // generate the number of sprites with offsets chosen randomly from the Perlin noise map
         for _ in 0..number_sprites {
@@ -158,55 +245,63 @@ pub fn initial_species_group_spawn(
             let x_coord: f32 = home.position.x + x_offset as f32;
             let y_coord: f32 = home.position.y + y_offset as f32;
             
-            let mut species_image: Handle<Image> = blue_species_handle.clone();
-            let mut aggressiveness: f32 = 0.;
-            let mut avoidance: f32 = 0.;
-            match race {
-                SpeciesRace::Blue => {
-                    species_image = blue_species_handle.clone();
-                    aggressiveness = 14.;
-                    avoidance = 30.;
-                },
-                SpeciesRace::Red => {
-                    species_image = red_species_handle.clone();
-                    aggressiveness = 8.;
-                    avoidance = 50.;
-                },
-                SpeciesRace::Yellow => {
-                    species_image = yellow_species_handle.clone();
-                    aggressiveness = 4.;
-                    avoidance = 50.;
-                },
-                SpeciesRace::Green => {
-                    species_image = green_species_handle.clone();
-                    aggressiveness = 1.;
-                    avoidance = 300.;
-                },
-            };
+            let mut species = Species::new(
+                Vec3::new(x_coord, y_coord, 10.), 
+                race, 
+                Some(home.position),
+                race_config.aggressiveness.sample(&mut *rng),
+                race_config.avoidance.sample(&mut *rng),
+            );
+            species.engineering = race_config.engineering.sample(&mut *rng);
+            species.tribalism = race_config.tribalism.sample(&mut *rng);
+            species.reproducibility = race_config.reproducibility.sample(&mut *rng);
+            species.fighting_score = race_config.fighting_score.sample(&mut *rng);
+            species.lifespan = race_config.lifespan.sample(&mut *rng);
+            species.perception_radius = configs.movement.perception_radius * race_config.perception.sample(&mut *rng);
+            species.lineage = lineages.found(race);
+            // the starting population has just grown up
+            species.age = configs.aging.maturity_sec;
+            let fight = Fight::new(race_config.attack.sample(&mut *rng), race_config.defense.sample(&mut *rng));
             
             // SPAWN ALL SPECIES COMPONENTS
             commands.spawn((
-                SpriteBundle {
-                    texture: species_image,
-                    transform: Transform {
-                         translation: Vec3::new(x_coord, y_coord, 1.),
-                         rotation: Quat::default(),
-                         scale: Vec3::splat(SPECIES_TEXTURE_SCALE),
-                    },
-                    ..default()},
-                Species::new(
-                    Vec3::new(x_coord, y_coord, 10.), 
-                    race, 
-                    home.position,
-                    aggressiveness,
-                    avoidance,
-                ),
-                WaterDesire::default(),
-                FoodDesire::default(),
-                Health::default(),
-                Fight::default(),
+                race_assets.species_sprite(race, Transform {
+                     translation: Vec3::new(x_coord, y_coord, 1.),
+                     rotation: Quat::default(),
+                     scale: Vec3::splat(SPECIES_TEXTURE_SCALE),
+                }),
+                species,
+                WaterDesire::new(&configs.thirst),
+                FoodDesire::new(&configs.hunger),
+                Health::full(&configs.health),
+                fight,
                 Reproduction::default(),
+                NavPath::default(),
+                Steering::default(),
             ));
         }
     }
 }
+
+
+
+/// Juveniles grow into their full size, elders decline and die of old age at the end of their lifespan
+pub fn grow_older(
+    mut query: Query<(&mut Species, &mut Health, &mut Transform)>,
+    config: Res<AgingConfig>,
+    time: Res<Time>,
+) {
+    for (mut sp, mut health, mut tf) in query.iter_mut() {
+        sp.age += time.delta_seconds();
+
+        if sp.stage(&config) == LifeStage::Elder {
+            health.damage(config.elder_decline_rate * time.delta_seconds(), DeathCause::OldAge, None);
+        }
+        tf.scale = Vec3::splat(SPECIES_TEXTURE_SCALE * sp.size(&config));
+
+        if sp.age >= sp.lifespan {
+            let remaining = health.val + 1.;
+            health.damage(remaining, DeathCause::OldAge, None);
+        }
+    }
+}
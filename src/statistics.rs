@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::species::*;
+use crate::race::Races;
+use crate::config::WorldConfig;
+use crate::clock::SimClock;
+use crate::snapshot::ResumeFrom;
+use crate::health::{DeathCause, Died};
+use crate::homebase::Homebase;
+
+const STATISTICS_FILE: &str = "statistics.csv";
+const POPULATION_SAMPLE_SEC: f32 = 5.0;
+
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatisticsConfig {
+    pub population_sample_sec: f32,
+}
+
+impl Default for StatisticsConfig {
+    fn default() -> Self {
+        Self {
+            population_sample_sec: POPULATION_SAMPLE_SEC,
+        }
+    }
+}
+
+
+/// Something worth recording in the statistics output, e.g. the start of a drought.
+/// Any system can send one of these, they are written to the csv with the time they happened.
+#[derive(Event)]
+pub struct StatisticsEvent {
+    pub category: String,
+    pub label: String,
+    pub value: f32,
+}
+
+impl StatisticsEvent {
+    pub fn new(category: &str, label: &str, value: f32) -> Self {
+        Self {
+            category: category.into(),
+            label: label.into(),
+            value,
+        }
+    }
+}
+
+
+/// The csv is only opened once there is something to write, so a run that is resumed right after startup
+/// still appends to the statistics of the run it was saved from
+#[derive(Resource)]
+pub struct Statistics {
+    pub sample_timer: Timer,
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    open_failed: bool,
+}
+
+impl Statistics {
+    pub fn new(config: &StatisticsConfig, path: PathBuf) -> Self {
+        Self {
+            sample_timer: Timer::from_seconds(config.population_sample_sec, TimerMode::Repeating),
+            path,
+            writer: None,
+            open_failed: false,
+        }
+    }
+
+    fn open(&mut self, resumed: bool) {
+        if self.writer.is_some() || self.open_failed { return; }
+
+        let append = resumed && self.path.exists();
+        let file = if append { OpenOptions::new().append(true).open(&self.path) } else { File::create(&self.path) };
+        match file {
+            Ok(file) => {
+                let mut writer = BufWriter::new(file);
+                if !append {
+                    let _ = writeln!(writer, "time,category,label,value");
+                }
+                self.writer = Some(writer);
+            },
+            Err(e) => {
+                warn!("Could not create the statistics file {:?}: {}", self.path, e);
+                self.open_failed = true;
+            },
+        }
+    }
+
+    fn write_row(&mut self, time: f32, category: &str, label: &str, value: f32) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writeln!(writer, "{:.2},{},{},{}", time, category, label, value) {
+                warn!("Could not write statistics row: {}", e);
+            }
+        }
+    }
+}
+
+
+
+pub fn setup_statistics(
+    mut commands: Commands,
+    world: Res<WorldConfig>,
+    config: Res<StatisticsConfig>,
+) {
+    let dir = PathBuf::from(&world.output_dir);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        warn!("Could not create output directory {:?}: {}", dir, e);
+    }
+    commands.insert_resource(Statistics::new(&config, dir.join(STATISTICS_FILE)));
+}
+
+
+
+pub fn write_statistics(
+    mut stats: ResMut<Statistics>,
+    mut events: EventReader<StatisticsEvent>,
+    mut died: EventReader<Died>,
+    query: Query<&Species>,
+    home_query: Query<&Homebase>,
+    races: Res<Races>,
+    clock: Res<SimClock>,
+    resume_from: Option<Res<ResumeFrom>>,
+    time: Res<Time>,
+) {
+    let now = clock.elapsed_seconds();
+    let mut wrote = false;
+
+    // the file is only opened once there is a row to write, see `Statistics`
+    stats.sample_timer.tick(time.delta());
+    if !events.is_empty() || !died.is_empty() || stats.sample_timer.just_finished() {
+        stats.open(resume_from.is_some());
+    }
+
+    for event in events.read() {
+        info!("[{:.1}s] {} {}: {}", now, event.category, event.label, event.value);
+        stats.write_row(now, &event.category, &event.label, event.value);
+        wrote = true;
+    }
+
+    // one row per death, too many to log. The value is the age it died at
+    for died in died.read() {
+        let cause = match died.cause {
+            Some(DeathCause::Structure(owner)) => format!("Structure of {}", races.get(owner).name),
+            Some(cause) => format!("{:?}", cause),
+            None => "Unknown".to_string(),
+        };
+        stats.write_row(now, "death", &format!("{}:{}", races.get(died.race).name, cause), died.age);
+        wrote = true;
+    }
+
+    // sample the population of every race at a fixed interval, so the events above can be lined up
+    // against population changes
+    if stats.sample_timer.just_finished() {
+        for (race, race_config) in races.iter() {
+            let count = query.iter().filter(|sp| sp.race == race).count();
+            stats.write_row(now, "population", &race_config.name, count as f32);
+        }
+        for home in home_query.iter() {
+            // a race can have several homebases, told apart by where they are
+            let name = format!("{}@{:.0}/{:.0}", races.get(home.species_race).name, home.position.x, home.position.y);
+            stats.write_row(now, "homebase", &format!("{}:food", name), home.food);
+            stats.write_row(now, "homebase", &format!("{}:water", name), home.water);
+            stats.write_row(now, "homebase", &format!("{}:health", name), home.health);
+            stats.write_row(now, "homebase", &format!("{}:wood", name), home.wood);
+            stats.write_row(now, "homebase", &format!("{}:stone", name), home.stone);
+        }
+        wrote = true;
+    }
+
+    if wrote {
+        if let Some(writer) = stats.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+use bevy::app::AppExit;
+use bevy::ecs::schedule::ScheduleLabel;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use crate::autosave::Autosave;
+use crate::clock::SimClock;
+use crate::config::{SimConfig, WorldConfig};
+use crate::flow_field::FlowFields;
+use crate::food_source::*;
+use crate::health::Blood;
+use crate::homebase::Homebase;
+use crate::lineage::Lineages;
+use crate::navigation::NavGrid;
+use crate::obstacle::Obstacle;
+use crate::engineering::ResourceNode;
+use crate::race::Races;
+use crate::replay::*;
+use crate::sim_rng::SimRng;
+use crate::species::Species;
+use crate::statistics::StatisticsEvent;
+use crate::water_source::WaterSource;
+use crate::weather::Weather;
+
+const OUTCOME_FILE: &str = "outcome.ron";
+const OUTCOMES_FILE: &str = "outcomes.csv";
+
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OnEnd {
+    Exit,
+    Restart, // generate a fresh world with the next seed and keep going
+}
+
+
+/// When a run is over. Every condition is off by default, so a run goes on until the window is closed
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminationConfig {
+    pub one_race_left: bool,
+    pub extinction: bool,
+    pub population_cap: Option<usize>,
+    pub time_limit_sec: Option<f64>, // simulated seconds
+    pub trait_convergence: Option<f32>, // every trait's standard deviation over its mean is below this
+    pub min_time_sec: f64, // the other conditions are only checked after this many simulated seconds
+    pub on_end: OnEnd,
+}
+
+impl Default for TerminationConfig {
+    fn default() -> Self {
+        Self {
+            one_race_left: false,
+            extinction: false,
+            population_cap: None,
+            time_limit_sec: None,
+            trait_convergence: None,
+            min_time_sec: 0.,
+            on_end: OnEnd::Exit,
+        }
+    }
+}
+
+impl TerminationConfig {
+    pub fn any(&self) -> bool {
+        self.one_race_left || self.extinction || self.population_cap.is_some() || self.time_limit_sec.is_some() || self.trait_convergence.is_some()
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EndCause {
+    OneRaceLeft,
+    Extinction,
+    PopulationCap,
+    TimeLimit,
+    TraitConvergence,
+}
+
+
+/// Mean of every trait over the species alive at the end of a run
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TraitMeans {
+    pub aggressiveness: f32,
+    pub avoidance: f32,
+    pub engineering: f32,
+    pub tribalism: f32,
+    pub reproducibility: f32,
+    pub fighting_score: f32,
+    #[serde(default)]
+    pub lifespan: f32,
+}
+
+impl TraitMeans {
+    pub fn of<'a>(species: impl Iterator<Item = &'a Species>) -> Self {
+        let mut sum = Self::default();
+        let mut n = 0;
+        for sp in species {
+            sum.aggressiveness += sp.aggressiveness;
+            sum.avoidance += sp.avoidance;
+            sum.engineering += sp.engineering;
+            sum.tribalism += sp.tribalism;
+            sum.reproducibility += sp.reproducibility;
+            sum.fighting_score += sp.fighting_score;
+            sum.lifespan += sp.lifespan;
+            n += 1;
+        }
+        if n == 0 { return sum; }
+        let n = n as f32;
+        Self {
+            aggressiveness: sum.aggressiveness / n,
+            avoidance: sum.avoidance / n,
+            engineering: sum.engineering / n,
+            tribalism: sum.tribalism / n,
+            reproducibility: sum.reproducibility / n,
+            fighting_score: sum.fighting_score / n,
+            lifespan: sum.lifespan / n,
+        }
+    }
+}
+
+
+/// How a run ended. Written to `<output_dir>/outcome.ron`, and appended to `outcomes.csv` when runs restart
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunOutcome {
+    pub run: u32, // counts the restarts
+    pub seed: u64,
+    pub end_time: f64,
+    pub cause: EndCause,
+    pub winner: Option<String>, // the only race left, if there is one
+    pub survivors: Vec<(String, usize)>, // alive at the end, for every race
+    pub trait_means: TraitMeans,
+}
+
+impl RunOutcome {
+    pub fn path(world: &WorldConfig) -> PathBuf {
+        PathBuf::from(&world.output_dir).join(OUTCOME_FILE)
+    }
+
+    fn write(&self, world: &WorldConfig) -> Result<(), String> {
+        let path = Self::path(world);
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("could not serialize the outcome: {}", e))?;
+        fs::write(&path, text).map_err(|e| format!("could not write {:?}: {}", path, e))?;
+
+        let path = PathBuf::from(&world.output_dir).join(OUTCOMES_FILE);
+        let new_file = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)
+            .map_err(|e| format!("could not open {:?}: {}", path, e))?;
+        if new_file {
+            writeln!(file, "run,seed,end_time,cause,winner,survivors").map_err(|e| e.to_string())?;
+        }
+        let survivors: Vec<String> = self.survivors.iter().map(|(name, n)| format!("{}:{}", name, n)).collect();
+        writeln!(file, "{},{},{:.2},{:?},{},{}", self.run, self.seed, self.end_time, self.cause, self.winner.clone().unwrap_or_default(), survivors.join(";"))
+            .map_err(|e| e.to_string())
+    }
+}
+
+
+/// The outcome of the current run once it has ended. The simulation doesn't step after that
+#[derive(Resource, Default)]
+pub struct Termination {
+    pub run: u32,
+    pub ended: Option<RunOutcome>,
+}
+
+
+/// Generates a fresh world: homebases, water, food, species and obstacles, in that order. Run at startup and again
+/// when a run restarts
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GenerateWorld;
+
+pub fn generate_world(world: &mut World) {
+    world.run_schedule(GenerateWorld);
+}
+
+
+
+/// Run condition of `SimSet`
+pub fn run_active(termination: Res<Termination>) -> bool {
+    termination.ended.is_none()
+}
+
+
+
+// the standard deviation of every trait over its mean is below the threshold
+fn traits_converged(species: &[&Species], threshold: f32) -> bool {
+    if species.len() < 2 { return false; }
+    let traits: [fn(&Species) -> f32; 7] = [
+        |sp| sp.aggressiveness,
+        |sp| sp.avoidance,
+        |sp| sp.engineering,
+        |sp| sp.tribalism,
+        |sp| sp.reproducibility,
+        |sp| sp.fighting_score,
+        |sp| sp.lifespan,
+    ];
+    let n = species.len() as f32;
+    traits.iter().all(|value| {
+        let mean = species.iter().map(|sp| value(sp)).sum::<f32>() / n;
+        let variance = species.iter().map(|sp| (value(sp) - mean).powi(2)).sum::<f32>() / n;
+        variance.sqrt() <= threshold * mean.abs()
+    })
+}
+
+
+/// Check the end conditions after every tick. When one is met the outcome is written and the app exits, or the world
+/// is regenerated at the start of the next frame
+pub fn check_termination(
+    mut termination: ResMut<Termination>,
+    config: Res<TerminationConfig>,
+    clock: Res<SimClock>,
+    rng: Res<SimRng>,
+    races: Res<Races>,
+    world: Res<WorldConfig>,
+    query: Query<&Species>,
+    mut stats_events: EventWriter<StatisticsEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if termination.ended.is_some() { return; }
+
+    let survivors: Vec<(String, usize)> = races.iter()
+        .map(|(race, race_config)| (race_config.name.clone(), query.iter().filter(|sp| sp.race == race).count()))
+        .collect();
+    let alive: Vec<String> = survivors.iter().filter(|(_, n)| *n > 0).map(|(name, _)| name.clone()).collect();
+    let population: usize = survivors.iter().map(|(_, n)| n).sum();
+    let checking = clock.elapsed >= config.min_time_sec;
+
+    let cause = if config.time_limit_sec.map_or(false, |limit| clock.elapsed >= limit) {
+        EndCause::TimeLimit
+    } else if !checking {
+        return;
+    } else if config.extinction && alive.is_empty() {
+        EndCause::Extinction
+    } else if config.one_race_left && alive.len() == 1 && races.list.len() > 1 {
+        EndCause::OneRaceLeft
+    } else if config.population_cap.map_or(false, |cap| population >= cap) {
+        EndCause::PopulationCap
+    } else if config.trait_convergence.map_or(false, |threshold| traits_converged(&query.iter().collect::<Vec<_>>(), threshold)) {
+        EndCause::TraitConvergence
+    } else {
+        return;
+    };
+
+    let outcome = RunOutcome {
+        run: termination.run,
+        seed: rng.seed,
+        end_time: clock.elapsed,
+        cause,
+        winner: if alive.len() == 1 { alive.into_iter().next() } else { None },
+        survivors,
+        trait_means: TraitMeans::of(query.iter()),
+    };
+    info!("Run {} ended at {:.1}s: {:?}, winner {:?}", outcome.run, outcome.end_time, outcome.cause, outcome.winner);
+    if let Err(e) = outcome.write(&world) {
+        warn!("Could not write the outcome: {}", e);
+    }
+    stats_events.send(StatisticsEvent::new("run", &format!("{:?}", outcome.cause), outcome.run as f32));
+
+    if config.on_end == OnEnd::Exit {
+        exit.send(AppExit);
+    }
+    termination.ended = Some(outcome);
+}
+
+
+
+/// Replace an ended run with a fresh world. The seed from the config is counted up for every restart, so a
+/// sequence of runs is still repeatable
+pub fn restart_world(world: &mut World) {
+    let restart = world.resource::<Termination>().ended.is_some()
+        && world.resource::<TerminationConfig>().on_end == OnEnd::Restart;
+    if !restart { return; }
+
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, Or<(With<Species>, With<FoodSource>, With<WaterSource>, With<Homebase>, With<Obstacle>, With<Blood>, With<ResourceNode>)>>()
+        .iter(world)
+        .collect();
+    for e in entities {
+        world.despawn(e);
+    }
+
+    let run = {
+        let mut termination = world.resource_mut::<Termination>();
+        termination.ended = None;
+        termination.run += 1;
+        termination.run
+    };
+    let config = world.resource::<SimConfig>().clone();
+    let seed = match config.world.seed {
+        Some(seed) => seed.wrapping_add(run as u64),
+        None => rand::random(),
+    };
+
+    world.insert_resource(SimRng::new(seed));
+    world.insert_resource(SimClock::default());
+    world.insert_resource(Weather::default());
+    world.insert_resource(FoodLocations::default());
+    world.insert_resource(FlowFields::default());
+    world.insert_resource(Autosave::default());
+    world.insert_resource(Lineages::default());
+    world.insert_resource(Replay::record(seed, config));
+    world.resource_mut::<PendingInputs>().0.clear();
+    let nav_grid = NavGrid::from_world(world);
+    world.insert_resource(nav_grid);
+
+    // the schedule is missing when the app was built without `WorldPlugin`
+    let _ = world.try_run_schedule(GenerateWorld);
+    info!("Restarted with seed {}, run {}", seed, run);
+}
@@ -1,8 +1,9 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 
 const TIMER_DURATION: f32 = 10.;
-pub const MAX_WATER: f32 = 5.;
+const MAX_WATER: f32 = 5.;
 const CAPACITY: f32 = 5.;
 const THIRST_RATE_HZ: f32 = 1.0;
 const IN_RANGE_DRINK: f32 = 40.;
@@ -10,7 +11,28 @@ const DRINK_RATE_HZ: f32 = 20.0;
 const GRACE_PERIOD_FRACTION: f32 = 0.8;
 
 
-#[derive(Component)]
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThirstConfig {
+    pub max_water: f32,
+    pub thirst_rate_hz: f32,
+    pub drink_range: f32,
+    pub drink_rate_hz: f32,
+}
+
+impl Default for ThirstConfig {
+    fn default() -> Self {
+        Self {
+            max_water: MAX_WATER,
+            thirst_rate_hz: THIRST_RATE_HZ,
+            drink_range: IN_RANGE_DRINK,
+            drink_rate_hz: DRINK_RATE_HZ,
+        }
+    }
+}
+
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct WaterDesire {
     pub val: f32,
     pub spawn_val: f32,
@@ -20,19 +42,22 @@ pub struct WaterDesire {
     pub is_consuming: bool,
     pub grace_period_percent: f32,
     pub timer: Timer,
+    #[serde(default)]
+    pub carried: f32, // water taken along for the homebase stockpile
 }
 
 impl WaterDesire {
-    pub fn default() -> Self {
+    pub fn new(config: &ThirstConfig) -> Self {
         Self {
-            val: MAX_WATER,
-            spawn_val: MAX_WATER,
+            val: config.max_water,
+            spawn_val: config.max_water,
             consume_rate: 1.,
-            in_range_drink: IN_RANGE_DRINK,
-            drink_rate_hz: DRINK_RATE_HZ,
+            in_range_drink: config.drink_range,
+            drink_rate_hz: config.drink_rate_hz,
             is_consuming: false,
             grace_period_percent: GRACE_PERIOD_FRACTION,
             timer: Timer::from_seconds(TIMER_DURATION, TimerMode::Once),
+            carried: 0.,
         }
     }
 }
@@ -40,6 +65,7 @@ impl WaterDesire {
 
 pub fn update_water_desire(
     mut query: Query<(Entity, &mut WaterDesire)>,
+    config: Res<ThirstConfig>,
     time: Res<Time>,
 ){
     for (e, mut w) in query.iter_mut() {
@@ -48,7 +74,7 @@ pub fn update_water_desire(
 
         // decrease its water if its not currently replenishing
         // if !w.is_consuming {
-            w.val -= THIRST_RATE_HZ * time.delta_seconds();
+            w.val -= config.thirst_rate_hz * time.delta_seconds();
         // }
     }
 }
@@ -1,14 +1,39 @@
 use bevy::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::{MAP_WIDTH, MAP_HEIGHT};
+use crate::config::WorldConfig;
+use crate::weather::*;
+use crate::sim_rng::SimRng;
 
 const NUMBER_SOURCES: u32 = 4;
-const RADIUS: f32 = 20.;
+pub const RADIUS: f32 = 20.;
 const CAPACITY: f32 = 10.;
+const REFILL_RATE_HZ: f32 = 0.5;
+pub const WATER_SPRITE_SCALE: f32 = 2.;
+pub const WATER_TEXTURE: &str = "textures/water_source_simple.png";
 
 
-#[derive(Component)]
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaterConfig {
+    pub number_sources: u32,
+    pub capacity: f32,
+    pub refill_rate_hz: f32,
+}
+
+impl Default for WaterConfig {
+    fn default() -> Self {
+        Self {
+            number_sources: NUMBER_SOURCES,
+            capacity: CAPACITY,
+            refill_rate_hz: REFILL_RATE_HZ,
+        }
+    }
+}
+
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct WaterSource {
     pub position: Vec3,
     pub capacity: f32,
@@ -18,39 +43,65 @@ pub struct WaterSource {
 
 // TODO make the water source radius proportional to the sprite size in pixels
 impl WaterSource {
-    pub fn new(position: Vec3) -> Self {
+    pub fn new(position: Vec3, capacity: f32) -> Self {
         Self {
             position,
-            capacity: CAPACITY,
-            value: CAPACITY,
+            capacity,
+            value: capacity,
             radius: RADIUS,
         }
     }
+
+    /// The sprite grows and shrinks with the radius
+    pub fn sprite_scale(&self) -> f32 {
+        WATER_SPRITE_SCALE * self.radius / RADIUS
+    }
 }
 
 
 pub fn spawn_water_sources(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    world: Res<WorldConfig>,
+    config: Res<WaterConfig>,
+    mut rng: ResMut<SimRng>,
 ){
-    let mut rng = rand::thread_rng();
-
-    let water_source_handle: Handle<Image> = asset_server.load("textures/water_source_simple.png");
+    let water_source_handle: Handle<Image> = asset_server.load(WATER_TEXTURE);
 
-    for _ in 0..NUMBER_SOURCES {
+    for _ in 0..config.number_sources {
 
-        let position = Vec3::new(rng.gen_range(-MAP_WIDTH/2.0..MAP_WIDTH/2.), rng.gen_range(-MAP_HEIGHT/2.0..MAP_HEIGHT/2.), -5.0);
+        let position = Vec3::new(
+            rng.gen_range(-world.map_width/2.0..world.map_width/2.),
+            rng.gen_range(-world.map_height/2.0..world.map_height/2.),
+            -5.0);
         commands.spawn((
             SpriteBundle {
                 texture: water_source_handle.clone(),
                 transform: Transform {
                      translation: position,
                      rotation: Quat::default(),
-                     scale: Vec3::splat(2.)},
+                     scale: Vec3::splat(WATER_SPRITE_SCALE)},
                 ..default()
             },
-            WaterSource::new(position),    
+            WaterSource::new(position, config.capacity),    
         ));
     }
 }
 
+
+
+// water sources slowly fill back up to capacity, except during a drought
+pub fn refill_water_sources(
+    mut query: Query<&mut WaterSource>,
+    weather: Res<Weather>,
+    config: Res<WaterConfig>,
+    time: Res<Time>,
+){
+    if weather.current == WeatherKind::Drought { return; }
+
+    for mut water_source in query.iter_mut() {
+        if water_source.value < water_source.capacity {
+            water_source.value = (water_source.value + config.refill_rate_hz * time.delta_seconds()).min(water_source.capacity);
+        }
+    }
+}
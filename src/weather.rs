@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::food_source::FoodSource;
+use crate::health::*;
+use crate::species::Species;
+use crate::water_source::*;
+use crate::statistics::StatisticsEvent;
+use crate::sim_rng::SimRng;
+
+// chance of each event starting, in events per simulated minute of clear weather
+const DROUGHT_PER_MIN: f32 = 0.2;
+const FLOOD_PER_MIN: f32 = 0.2;
+const STORM_PER_MIN: f32 = 0.5;
+const DROUGHT_DURATION_SEC: f32 = 60.;
+const FLOOD_DURATION_SEC: f32 = 30.;
+const STORM_DURATION_SEC: f32 = 20.;
+
+const FLOOD_WATER_SCALE: f32 = 5.0; // sprite scale of the water sources while flooded
+const STORM_MOVEMENT_FACTOR: f32 = 0.5;
+const STORM_PERCEPTION_FACTOR: f32 = 0.4;
+const STORM_DAMAGE_RATE: f32 = 0.2; // health per second, faster than the fed species heal
+
+
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum WeatherKind {
+    Clear,
+    Drought,
+    Flood,
+    Storm,
+}
+
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+pub struct Weather {
+    pub current: WeatherKind,
+    pub timer: Timer,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            current: WeatherKind::Clear,
+            timer: Timer::from_seconds(0., TimerMode::Once),
+        }
+    }
+}
+
+impl Weather {
+    /// Scales how far the species move each update. Storms slow everyone down
+    pub fn movement_factor(&self, config: &WeatherConfig) -> f32 {
+        match self.current {
+            WeatherKind::Storm => config.storm_movement_factor,
+            _ => 1.0,
+        }
+    }
+
+    /// Scales how far the species can see
+    pub fn perception_factor(&self, config: &WeatherConfig) -> f32 {
+        match self.current {
+            WeatherKind::Storm => config.storm_perception_factor,
+            _ => 1.0,
+        }
+    }
+}
+
+
+/// How often the weather events happen, how long they last and how strong they are.
+/// The frequencies can also be changed from the debug menu
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub enabled: bool,
+    pub drought_per_min: f32,
+    pub flood_per_min: f32,
+    pub storm_per_min: f32,
+    pub drought_duration: f32,
+    pub flood_duration: f32,
+    pub storm_duration: f32,
+    pub flood_water_scale: f32,
+    pub storm_movement_factor: f32,
+    pub storm_perception_factor: f32,
+    pub storm_damage_rate: f32, // health per second away from the homebase, within `health.home_radius` is sheltered
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            drought_per_min: DROUGHT_PER_MIN,
+            flood_per_min: FLOOD_PER_MIN,
+            storm_per_min: STORM_PER_MIN,
+            drought_duration: DROUGHT_DURATION_SEC,
+            flood_duration: FLOOD_DURATION_SEC,
+            storm_duration: STORM_DURATION_SEC,
+            flood_water_scale: FLOOD_WATER_SCALE,
+            storm_movement_factor: STORM_MOVEMENT_FACTOR,
+            storm_perception_factor: STORM_PERCEPTION_FACTOR,
+            storm_damage_rate: STORM_DAMAGE_RATE,
+        }
+    }
+}
+
+
+
+/// Only one event happens at a time. While the weather is clear, roll for each kind of event every update
+pub fn update_weather(
+    mut weather: ResMut<Weather>,
+    settings: Res<WeatherConfig>,
+    mut stats: EventWriter<StatisticsEvent>,
+    mut rng: ResMut<SimRng>,
+    time: Res<Time>,
+) {
+    if weather.current != WeatherKind::Clear {
+        weather.timer.tick(time.delta());
+        if weather.timer.finished() {
+            stats.send(StatisticsEvent::new("weather_end", &format!("{:?}", weather.current), weather.timer.duration().as_secs_f32()));
+            weather.current = WeatherKind::Clear;
+        }
+        return;
+    }
+
+    if !settings.enabled { return; }
+
+    let minutes = time.delta_seconds() / 60.;
+    let candidates = [
+        (WeatherKind::Drought, settings.drought_per_min, settings.drought_duration),
+        (WeatherKind::Flood, settings.flood_per_min, settings.flood_duration),
+        (WeatherKind::Storm, settings.storm_per_min, settings.storm_duration),
+    ];
+    for (kind, per_min, duration) in candidates {
+        if rng.gen_range(0.0..1.0) < per_min * minutes {
+            weather.current = kind;
+            weather.timer = Timer::from_seconds(duration, TimerMode::Once);
+            stats.send(StatisticsEvent::new("weather_start", &format!("{:?}", kind), duration));
+            break;
+        }
+    }
+}
+
+
+
+/// Floods expand the water sources as much as their sprites, and drown the food that is under water when the
+/// flood starts
+pub fn flood_water_sources(
+    weather: Res<Weather>,
+    config: Res<WeatherConfig>,
+    mut water_query: Query<(&mut WaterSource, &mut Transform)>,
+    food_query: Query<(Entity, &FoodSource)>,
+    mut commands: Commands,
+) {
+    if weather.current != WeatherKind::Flood && !weather.is_changed() { return; }
+
+    let mut drowned: Vec<Entity> = Vec::new();
+    for (mut water_source, mut tf) in water_query.iter_mut() {
+        if weather.current == WeatherKind::Flood {
+            let flooded = RADIUS * config.flood_water_scale / WATER_SPRITE_SCALE;
+            if water_source.radius == flooded { continue; }
+            water_source.radius = flooded;
+
+            for (food_e, food) in food_query.iter() {
+                // two flooded sources can overlap
+                let under_water = (food.position.xy() - water_source.position.xy()).length() < water_source.radius;
+                if under_water && !drowned.contains(&food_e) {
+                    drowned.push(food_e);
+                }
+            }
+        } else {
+            water_source.radius = RADIUS;
+        }
+        tf.scale = Vec3::splat(water_source.sprite_scale());
+    }
+
+    for food_e in drowned {
+        commands.entity(food_e).despawn();
+    }
+}
+
+
+
+/// Storms wear down the species caught outside. Those at their homebase are sheltered, nomads have nowhere to hide
+pub fn storm_damage(
+    mut query: Query<(&mut Health, &Species)>,
+    weather: Res<Weather>,
+    config: Res<WeatherConfig>,
+    health_config: Res<HealthConfig>,
+    time: Res<Time>,
+) {
+    if weather.current != WeatherKind::Storm || config.storm_damage_rate <= 0. { return; }
+
+    let damage = config.storm_damage_rate * time.delta_seconds();
+    for (mut health, sp) in query.iter_mut() {
+        let sheltered = sp.homebase.is_some_and(|home| (sp.position.xy() - home.xy()).length() < health_config.home_radius);
+        if !sheltered {
+            health.damage(damage, DeathCause::Environment, None);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::engineering::*;
+use evolution_sim_bevy::fight::FightConfig;
+use evolution_sim_bevy::food_desire::*;
+use evolution_sim_bevy::homebase::*;
+use evolution_sim_bevy::species::Species;
+use evolution_sim_bevy::water_desire::WaterDesire;
+
+
+fn homebase(app: &App, e: Entity) -> &Homebase {
+    app.world.get::<Homebase>(e).expect("the homebase is gone")
+}
+
+
+#[test]
+fn species_stock_their_homebase_and_eat_from_it() {
+    let mut app = test_app(test_config("stockpile"), ColonyPlugin);
+    let hunger = HungerConfig::default();
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let enemy_home = spawn_homebase(&mut app, BLUE, Vec3::new(1000., 0., 0.));
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<FoodDesire>(e).unwrap().val = hunger.max_hunger + 10.;
+    app.world.get_mut::<WaterDesire>(e).unwrap().carried = 3.;
+
+    run_ticks(&mut app, 1);
+
+    // everything beyond a full stomach is dropped off
+    assert_eq!(homebase(&app, home).food, 10.);
+    assert_eq!(homebase(&app, home).water, 3.);
+    assert_eq!(homebase(&app, home).population, 1);
+    assert_eq!(homebase(&app, enemy_home).population, 0);
+    assert_eq!(app.world.get::<FoodDesire>(e).unwrap().val, hunger.max_hunger);
+    assert_eq!(app.world.get::<WaterDesire>(e).unwrap().carried, 0.);
+
+    // a hungry species fills up from the stockpile
+    app.world.get_mut::<FoodDesire>(e).unwrap().val = -1.;
+    run_ticks(&mut app, 1);
+    assert_eq!(app.world.get::<FoodDesire>(e).unwrap().val, hunger.max_hunger);
+    assert_eq!(homebase(&app, home).food, 10. - hunger.max_hunger - 1.);
+}
+
+
+#[test]
+fn species_away_from_home_do_not_use_the_stockpile() {
+    let mut app = test_app(test_config("stockpile_away"), ColonyPlugin);
+    let config = HomebaseConfig::default();
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Homebase>(home).unwrap().food = 10.;
+    let e = spawn_species(&mut app, RED, Vec3::new(config.radius * 2., 0., 0.));
+    app.world.get_mut::<FoodDesire>(e).unwrap().val = -1.;
+
+    run_ticks(&mut app, 1);
+
+    assert_eq!(homebase(&app, home).food, 10.);
+    assert_eq!(homebase(&app, home).population, 1);
+    assert_eq!(app.world.get::<FoodDesire>(e).unwrap().val, -1.);
+}
+
+
+#[test]
+fn enemies_attack_homebases() {
+    let mut app = test_app(test_config("raid"), CombatPlugin);
+    let config = FightConfig::default();
+    let home = spawn_homebase(&mut app, BLUE, Vec3::ZERO);
+    let own_home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    spawn_species(&mut app, RED, Vec3::ZERO);
+
+    run_ticks(&mut app, 30);
+
+    // one hit within the cooldown, homebases have no defense
+    let full = HomebaseConfig::default().max_health;
+    assert_eq!(homebase(&app, home).health, full - config.hit_damage);
+    assert_eq!(homebase(&app, own_home).health, full);
+}
+
+
+#[test]
+fn survivors_of_a_destroyed_homebase_move_and_the_raiders_take_the_stockpile() {
+    let mut app = test_app(test_config("relocate"), ColonyPlugin);
+    let config = HomebaseConfig::default();
+    let ruin = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let raiders = spawn_homebase(&mut app, BLUE, Vec3::new(1000., 0., 0.));
+    {
+        let mut home = app.world.get_mut::<Homebase>(ruin).unwrap();
+        home.health = 0.;
+        home.food = 50.;
+        home.last_raider = Some(BLUE);
+    }
+    let survivors: Vec<Entity> = (0..config.relocate_population).map(|_| spawn_species(&mut app, RED, Vec3::ZERO)).collect();
+
+    run_ticks(&mut app, 1);
+
+    assert!(app.world.get::<Homebase>(ruin).is_none());
+    assert_eq!(homebase(&app, raiders).food, 50.);
+    let new_home = app.world.get::<Species>(survivors[0]).unwrap().homebase.expect("the survivors are nomads");
+    assert!(new_home.xy().distance(Vec2::new(1000., 0.)) >= config.site_clearance);
+    for e in survivors.iter() {
+        assert_eq!(app.world.get::<Species>(*e).unwrap().homebase, Some(new_home));
+    }
+
+    run_ticks(&mut app, 1);
+    let homes: Vec<Homebase> = app.world.query::<&Homebase>().iter(&app.world).cloned().collect();
+    let moved = homes.iter().find(|home| home.species_race == RED).expect("no new homebase");
+    assert_eq!(moved.position, new_home);
+    assert_eq!(moved.health, config.max_health);
+    assert_eq!(moved.population, config.relocate_population);
+}
+
+
+#[test]
+fn too_few_survivors_become_nomads() {
+    let mut app = test_app(test_config("nomads"), ColonyPlugin);
+    let ruin = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Homebase>(ruin).unwrap().health = 0.;
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+
+    run_ticks(&mut app, 2);
+
+    assert_eq!(app.world.get::<Species>(e).unwrap().homebase, None);
+    assert_eq!(app.world.query::<&Homebase>().iter(&app.world).count(), 0);
+}
+
+
+
+#[test]
+fn crowded_homebases_found_colonies() {
+    let mut config = test_config("colonies");
+    config.homebase.crowding_population = 3;
+    config.homebase.colony_size = 2;
+    let mut app = test_app(config.clone(), ColonyPlugin);
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let species: Vec<Entity> = (0..5).map(|_| spawn_species(&mut app, RED, Vec3::ZERO)).collect();
+
+    // the population is counted on the first tick, the colonists leave on the second
+    run_ticks(&mut app, 2);
+
+    let sites: Vec<Vec3> = species.iter()
+        .filter_map(|e| app.world.get::<Species>(*e).unwrap().homebase)
+        .filter(|site| *site != Vec3::ZERO)
+        .collect();
+    assert_eq!(sites.len(), 2);
+    assert_eq!(sites[0], sites[1]);
+    assert!(sites[0].xy().length() >= config.homebase.site_clearance);
+    assert_eq!(homebase(&app, home).population, 3);
+
+    // the colony is founded when the first colonist arrives
+    let colonist = *species.iter().find(|e| app.world.get::<Species>(**e).unwrap().homebase == Some(sites[0])).unwrap();
+    app.world.get_mut::<Species>(colonist).unwrap().position = sites[0];
+    run_ticks(&mut app, 2);
+
+    let homes: Vec<Homebase> = app.world.query::<&Homebase>().iter(&app.world).cloned().collect();
+    assert_eq!(homes.len(), 2);
+    let colony = homes.iter().find(|home| home.position == sites[0]).expect("no colony");
+    assert_eq!(colony.species_race, RED);
+    assert_eq!(colony.population, 2);
+}
+
+
+#[test]
+fn a_homebase_waits_before_sending_off_the_next_colony() {
+    let mut config = test_config("split_cooldown");
+    config.homebase.crowding_population = 3;
+    config.homebase.colony_size = 1;
+    let mut app = test_app(config, ColonyPlugin);
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let species: Vec<Entity> = (0..6).map(|_| spawn_species(&mut app, RED, Vec3::ZERO)).collect();
+
+    run_ticks(&mut app, 5);
+
+    let colonists = species.iter()
+        .filter(|e| app.world.get::<Species>(**e).unwrap().homebase != Some(Vec3::ZERO))
+        .count();
+    assert_eq!(colonists, 1);
+    assert!(homebase(&app, home).split_cooldown > 0.);
+}
+
+
+#[test]
+fn raiders_loot_into_the_room_their_storage_makes() {
+    let mut app = test_app(test_config("loot"), ColonyPlugin);
+    let config = HomebaseConfig::default();
+    let engineering = EngineeringConfig::default();
+    let ruin = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let raiders = spawn_homebase(&mut app, BLUE, Vec3::new(1000., 0., 0.));
+    let storage = Structure::new(StructureKind::Storage, homebase(&app, raiders), 1, &engineering);
+    app.world.spawn(storage);
+    {
+        let mut home = app.world.get_mut::<Homebase>(ruin).unwrap();
+        home.health = 0.;
+        home.food = config.food_capacity + engineering.storage_capacity + 10.;
+        home.last_raider = Some(BLUE);
+    }
+
+    run_ticks(&mut app, 1);
+
+    assert_eq!(homebase(&app, raiders).food, config.food_capacity + engineering.storage_capacity);
+}
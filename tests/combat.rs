@@ -0,0 +1,170 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::fight::*;
+use evolution_sim_bevy::food_desire::*;
+use evolution_sim_bevy::food_source::FoodSource;
+use evolution_sim_bevy::health::*;
+use evolution_sim_bevy::species::Species;
+
+
+#[test]
+fn enemies_in_range_lose_health() {
+    let mut app = test_app(test_config("enemies_in_range"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+
+    run_ticks(&mut app, 30);
+
+    // one hit each within the cooldown. The default attack equals the defense, which takes half of the damage
+    let expected = HealthConfig::default().max_health - config.hit_damage / 2.;
+    assert!((health(&app, red) - expected).abs() < 1e-4, "red health {}", health(&app, red));
+    assert!((health(&app, blue) - expected).abs() < 1e-4, "blue health {}", health(&app, blue));
+
+    run_ticks(&mut app, (config.hit_cooldown_sec / TICK).ceil() as u64);
+    assert!((health(&app, red) - (expected - config.hit_damage / 2.)).abs() < 1e-4, "red health {}", health(&app, red));
+}
+
+
+#[test]
+fn timid_species_do_not_start_fights() {
+    let mut app = test_app(test_config("timid"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+    app.world.get_mut::<Species>(blue).unwrap().aggressiveness = 0.;
+
+    run_ticks(&mut app, 30);
+
+    let full = HealthConfig::default().max_health;
+    assert_eq!(health(&app, red), full);
+    assert!(health(&app, blue) < full);
+}
+
+
+#[test]
+fn juveniles_do_not_start_fights() {
+    let mut app = test_app(test_config("juvenile"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+    app.world.get_mut::<Species>(blue).unwrap().age = 0.;
+
+    run_ticks(&mut app, 30);
+
+    let full = HealthConfig::default().max_health;
+    assert_eq!(health(&app, red), full);
+    assert!(health(&app, blue) < full);
+}
+
+
+#[test]
+fn defense_reduces_damage() {
+    let mut app = test_app(test_config("defense"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+    app.world.get_mut::<Fight>(blue).unwrap().defense_val = 3.;
+
+    run_ticks(&mut app, 1);
+
+    // attack 1 against defense 3 takes a quarter of the damage
+    let full = HealthConfig::default().max_health;
+    assert!((health(&app, blue) - (full - config.hit_damage / 4.)).abs() < 1e-4, "blue health {}", health(&app, blue));
+    assert!((health(&app, red) - (full - config.hit_damage / 2.)).abs() < 1e-4, "red health {}", health(&app, red));
+}
+
+
+#[test]
+fn hits_can_injure() {
+    let mut config = test_config("injuries");
+    config.health.injury_chance = 1.;
+    let mut app = test_app(config, CombatPlugin);
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(FightConfig::default().attack_range - 1., 0., 0.));
+
+    run_ticks(&mut app, 1);
+
+    let health_config = HealthConfig::default();
+    for e in [red, blue] {
+        let health = app.world.get::<Health>(e).unwrap();
+        assert!(health.injury.is_some());
+        assert_eq!(health.injury_time, health_config.injury_sec);
+        assert!(health.speed_factor(&health_config) * health.attack_factor(&health_config) < 1.);
+    }
+}
+
+
+#[test]
+fn losing_species_flee() {
+    let mut app = test_app(test_config("flee"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+    let full = HealthConfig::default().max_health;
+    app.world.get_mut::<Health>(blue).unwrap().val = config.flee_health * full;
+
+    run_ticks(&mut app, 1);
+
+    let fight = app.world.get::<Fight>(blue).unwrap();
+    assert!(fight.fleeing());
+    assert_eq!(fight.flee_from, Some(Vec3::ZERO));
+
+    // too weak to hit back
+    run_ticks(&mut app, (config.hit_cooldown_sec / TICK).ceil() as u64);
+    assert_eq!(health(&app, red), full);
+}
+
+
+#[test]
+fn enemies_out_of_range_and_allies_do_not_fight() {
+    let mut app = test_app(test_config("no_fight"), CombatPlugin);
+    let range = FightConfig::default().attack_range;
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let far_blue = spawn_species(&mut app, BLUE, Vec3::new(range + 1., 0., 0.));
+    let ally = spawn_species(&mut app, RED, Vec3::new(1., 0., 0.));
+
+    run_ticks(&mut app, 30);
+
+    let full = HealthConfig::default().max_health;
+    for e in [red, far_blue, ally] {
+        assert_eq!(health(&app, e), full);
+    }
+}
+
+
+#[test]
+fn a_kill_feeds_the_killer_once_and_leaves_a_corpse() {
+    let mut app = test_app(test_config("kill_credit"), CombatPlugin);
+    let config = FightConfig::default();
+    let red = spawn_species(&mut app, RED, Vec3::ZERO);
+    let blue = spawn_species(&mut app, BLUE, Vec3::new(config.attack_range - 1., 0., 0.));
+    app.world.get_mut::<Health>(blue).unwrap().val = 0.1;
+    let hunger = app.world.get::<FoodDesire>(red).unwrap().val;
+
+    run_ticks(&mut app, 30);
+
+    assert!(app.world.get_entity(blue).is_none());
+    // the killing hit cost some food too
+    let expected = hunger + config.species_food_eat_restore - MetabolismConfig::default().hit_cost;
+    assert!((app.world.get::<FoodDesire>(red).unwrap().val - expected).abs() < 1e-4);
+    let corpses: Vec<FoodSource> = app.world.query::<&FoodSource>().iter(&app.world).cloned().collect();
+    assert_eq!(corpses.len(), 1);
+    assert!(corpses[0].is_corpse());
+}
+
+
+#[test]
+fn species_below_zero_health_die() {
+    let mut app = test_app(test_config("death"), CombatPlugin);
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Health>(e).unwrap().val = -1.;
+
+    run_ticks(&mut app, 1);
+
+    assert!(app.world.get_entity(e).is_none());
+    assert_eq!(count_species(&mut app), 0);
+}
@@ -0,0 +1,140 @@
+//! Shared setup for the integration tests: a headless app with only the plugins a test asks for, hand-placed
+//! species, and a way to advance the simulation by whole ticks
+
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy::app::Plugins;
+use bevy::time::TimeUpdateStrategy;
+use std::path::PathBuf;
+use std::time::Duration;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::behavior::Steering;
+use evolution_sim_bevy::clock::*;
+use evolution_sim_bevy::config::SimConfig;
+use evolution_sim_bevy::fight::Fight;
+use evolution_sim_bevy::food_desire::FoodDesire;
+use evolution_sim_bevy::health::Health;
+use evolution_sim_bevy::homebase::Homebase;
+use evolution_sim_bevy::navigation::NavPath;
+use evolution_sim_bevy::race::*;
+use evolution_sim_bevy::reproduce::Reproduction;
+use evolution_sim_bevy::species::*;
+use evolution_sim_bevy::water_desire::WaterDesire;
+
+/// Seconds in one simulation tick
+pub const TICK: f32 = 1. / SIM_TICK_HZ as f32;
+
+pub const RED: SpeciesRace = SpeciesRace(0);
+pub const BLUE: SpeciesRace = SpeciesRace(1);
+
+
+/// Defaults with the random parts switched off, writing into its own temporary directory
+pub fn test_config(name: &str) -> SimConfig {
+    let mut config = SimConfig::default();
+    let dir: PathBuf = std::env::temp_dir().join("evolution-sim-tests").join(name);
+    config.world.output_dir = dir.to_string_lossy().into();
+    config.world.seed = Some(1);
+    config.weather.enabled = false;
+    config.autosave.enabled = false;
+    config.health.injury_chance = 0.;
+    config.reproduction.mutation = 0.;
+    // the test species have an engineering of 1, make them builders
+    config.engineering.builder_engineering = 1.;
+    config
+}
+
+
+fn race(name: &str, color: (f32, f32, f32)) -> RaceConfig {
+    RaceConfig {
+        name: name.into(),
+        color,
+        texture: None,
+        homebase_texture: None,
+        starting_population: (0, 0),
+        homebase_region: HomebaseRegion { min: (0., 0.), max: (1., 1.) },
+        aggressiveness: TraitRange::default(),
+        avoidance: TraitRange::default(),
+        engineering: TraitRange::default(),
+        tribalism: TraitRange::default(),
+        reproducibility: TraitRange::default(),
+        fighting_score: TraitRange::default(),
+        perception: TraitRange::default(),
+        attack: TraitRange::default(),
+        defense: TraitRange::default(),
+        lifespan: TraitRange { min: LIFESPAN_SEC, max: LIFESPAN_SEC },
+    }
+}
+
+pub fn test_races() -> Races {
+    Races { list: vec![race("Red", (1., 0., 0.)), race("Blue", (0., 0., 1.))] }
+}
+
+
+/// A headless app with the core of the simulation and nothing else. Every `update` after the first is exactly one
+/// simulation tick. The startup systems have run when this returns, so species can be spawned right away
+pub fn test_app<M>(config: SimConfig, plugins: impl Plugins<M>) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(AssetPlugin::default())
+        .init_asset::<Image>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1. / SIM_TICK_HZ)))
+        .add_plugins(CorePlugin { config, races: test_races() })
+        .add_plugins(plugins);
+    app.update();
+    app
+}
+
+
+/// Spawn a species with full health, food and water, the same way the world generation does
+pub fn spawn_species(app: &mut App, race: SpeciesRace, position: Vec3) -> Entity {
+    let config = app.world.resource::<SimConfig>().clone();
+    let transform = Transform {
+        translation: position,
+        rotation: Quat::default(),
+        scale: Vec3::splat(SPECIES_TEXTURE_SCALE),
+    };
+    let sprite = app.world.resource::<RaceAssets>().species_sprite(race, transform);
+    // grown up, so it can fight and reproduce
+    let mut species = Species::new(position, race, Some(Vec3::ZERO), 1., 1.);
+    species.age = config.aging.maturity_sec;
+    app.world.spawn((
+        sprite,
+        species,
+        WaterDesire::new(&config.thirst),
+        FoodDesire::new(&config.hunger),
+        Health::full(&config.health),
+        Fight::default(),
+        Reproduction::default(),
+        NavPath::default(),
+        Steering::default(),
+    )).id()
+}
+
+
+/// Spawn a homebase without a sprite. The species from `spawn_species` live at the origin
+pub fn spawn_homebase(app: &mut App, race: SpeciesRace, position: Vec3) -> Entity {
+    let config = app.world.resource::<SimConfig>().homebase.clone();
+    app.world.spawn(Homebase::new(position, race, &config)).id()
+}
+
+
+/// Advance the simulation by exactly this many ticks
+pub fn run_ticks(app: &mut App, ticks: u64) {
+    let target = app.world.resource::<SimClock>().frame + ticks;
+    for _ in 0..ticks * 2 + 10 {
+        if app.world.resource::<SimClock>().frame >= target { break; }
+        app.update();
+    }
+    assert_eq!(app.world.resource::<SimClock>().frame, target, "the simulation did not advance one tick per update");
+}
+
+
+pub fn health(app: &App, e: Entity) -> f32 {
+    app.world.get::<Health>(e).expect("the species is gone").val
+}
+
+
+pub fn count_species(app: &mut App) -> usize {
+    app.world.query::<&Species>().iter(&app.world).count()
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::config::SimConfig;
+use evolution_sim_bevy::food_desire::*;
+use evolution_sim_bevy::replay::*;
+use evolution_sim_bevy::species::Species;
+use evolution_sim_bevy::weather::WeatherConfig;
+
+
+#[test]
+fn config_changes_only_apply_what_changed() {
+    let mut app = test_app(test_config("reload"), NeedsPlugin);
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+    // inherited, so it is no longer the config value
+    app.world.get_mut::<Species>(e).unwrap().perception_radius = 42.;
+    // moved in the debug menu
+    app.world.resource_mut::<WeatherConfig>().flood_per_min = 3.;
+    run_ticks(&mut app, 1);
+
+    let mut config = app.world.resource::<SimConfig>().clone();
+    config.hunger.eat_radius += 5.;
+    let old_perception = config.movement.perception_radius;
+    config.movement.perception_radius *= 2.;
+    app.world.resource_mut::<PendingInputs>().push(SimInput::Config(config.clone()));
+    run_ticks(&mut app, 2);
+
+    assert_eq!(app.world.resource::<HungerConfig>().eat_radius, config.hunger.eat_radius);
+    assert_eq!(app.world.get::<FoodDesire>(e).unwrap().in_range_eat, config.hunger.eat_radius);
+    assert_eq!(app.world.get::<Species>(e).unwrap().perception_radius, 42. * config.movement.perception_radius / old_perception);
+    assert_eq!(app.world.resource::<WeatherConfig>().flood_per_min, 3.);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::engineering::{EngineeringConfig, Material, ResourceNode, Structure, StructureKind};
+use evolution_sim_bevy::fight::FightConfig;
+use evolution_sim_bevy::food_desire::*;
+use evolution_sim_bevy::health::{DeathCause, Died, Health};
+use evolution_sim_bevy::homebase::*;
+use evolution_sim_bevy::species::Species;
+
+
+fn spawn_node(app: &mut App, material: Material, position: Vec3, amount: f32) -> Entity {
+    app.world.spawn(ResourceNode { position, material, amount }).id()
+}
+
+fn structures(app: &mut App) -> Vec<Structure> {
+    app.world.query::<&Structure>().iter(&app.world).cloned().collect()
+}
+
+
+#[test]
+fn builders_gather_from_resource_nodes() {
+    let mut app = test_app(test_config("gather"), BehaviorPlugin);
+    let config = EngineeringConfig::default();
+    let node = spawn_node(&mut app, Material::Stone, Vec3::new(5., 0., 0.), config.carry_amount + 1.);
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+
+    run_ticks(&mut app, 2);
+
+    assert_eq!(app.world.get::<Species>(e).unwrap().carrying, Some((Material::Stone, config.carry_amount)));
+    assert_eq!(app.world.get::<ResourceNode>(node).unwrap().amount, 1.);
+    let food = app.world.get::<FoodDesire>(e).unwrap().val;
+    assert!(food <= HungerConfig::default().max_hunger - MetabolismConfig::default().gather_cost, "gathering is free, food {}", food);
+
+    // carrying, so the rest is left for the next trip
+    run_ticks(&mut app, 2);
+    assert_eq!(app.world.get::<ResourceNode>(node).unwrap().amount, 1.);
+}
+
+
+#[test]
+fn materials_are_dropped_off_at_home_and_built_into_walls() {
+    let mut app = test_app(test_config("build"), ColonyPlugin);
+    let config = EngineeringConfig::default();
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Species>(e).unwrap().carrying = Some((Material::Stone, config.wall_stone));
+
+    run_ticks(&mut app, 2);
+
+    assert_eq!(app.world.get::<Species>(e).unwrap().carrying, None);
+    assert_eq!(app.world.get::<Homebase>(home).unwrap().stone, 0.);
+    let built = structures(&mut app);
+    assert_eq!(built.len(), 1);
+    assert_eq!(built[0].kind, StructureKind::Wall);
+    assert_eq!(built[0].race, RED);
+    assert_ne!(built[0].slot % config.gate_every, 0, "gates are left open");
+}
+
+
+#[test]
+fn nothing_is_built_without_a_builder_at_home() {
+    let mut app = test_app(test_config("build_away"), ColonyPlugin);
+    let config = EngineeringConfig::default();
+    let home = spawn_homebase(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Homebase>(home).unwrap().stone = config.wall_stone;
+    spawn_species(&mut app, RED, Vec3::new(HomebaseConfig::default().radius * 2., 0., 0.));
+
+    run_ticks(&mut app, 2);
+
+    assert!(structures(&mut app).is_empty());
+    assert_eq!(app.world.get::<Homebase>(home).unwrap().stone, config.wall_stone);
+}
+
+
+#[test]
+fn towers_shoot_enemies_in_range() {
+    let mut app = test_app(test_config("tower"), CombatPlugin);
+    let config = EngineeringConfig::default();
+    let home = Homebase::new(Vec3::ZERO, RED, &HomebaseConfig::default());
+    let tower = Structure::new(StructureKind::Tower, &home, 1, &config);
+    let near = tower.position + Vec3::new(config.tower_range / 2., 0., 0.);
+    app.world.spawn(tower);
+    let enemy = spawn_species(&mut app, BLUE, near);
+    let friend = spawn_species(&mut app, RED, near + Vec3::new(0., 40., 0.));
+    let full = health(&app, enemy);
+
+    run_ticks(&mut app, 1);
+
+    assert_eq!(health(&app, enemy), full - config.tower_damage);
+    assert_eq!(health(&app, friend), full);
+}
+
+
+#[test]
+fn raiders_tear_down_structures_before_the_homebase() {
+    let mut app = test_app(test_config("siege"), CombatPlugin);
+    let config = EngineeringConfig::default();
+    let home_e = spawn_homebase(&mut app, BLUE, Vec3::ZERO);
+    let home = app.world.get::<Homebase>(home_e).unwrap().clone();
+    let wall = Structure::new(StructureKind::Wall, &home, 1, &config);
+    let position = wall.position;
+    let wall_e = app.world.spawn(wall).id();
+    spawn_species(&mut app, RED, position);
+
+    run_ticks(&mut app, 30);
+
+    let hit_damage = app.world.resource::<FightConfig>().hit_damage;
+    assert_eq!(app.world.get::<Structure>(wall_e).unwrap().health, config.wall_health - hit_damage);
+    assert_eq!(app.world.get::<Homebase>(home_e).unwrap().health, home.full_health);
+}
+
+
+#[test]
+fn raiders_hit_the_nearest_structure_in_reach() {
+    let mut app = test_app(test_config("siege_nearest"), CombatPlugin);
+    let config = EngineeringConfig::default();
+    let home = Homebase::new(Vec3::ZERO, BLUE, &HomebaseConfig::default());
+    let raider_at = Structure::new(StructureKind::Wall, &home, 1, &config).position;
+    let attack_range = app.world.resource::<FightConfig>().attack_range;
+    // the farther one is spawned first, so it comes first in the query
+    let mut far = Structure::new(StructureKind::Wall, &home, 2, &config);
+    far.position = raider_at + Vec3::new(attack_range, 0., 0.);
+    let far = app.world.spawn(far).id();
+    let mut near = Structure::new(StructureKind::Wall, &home, 1, &config);
+    near.position = raider_at + Vec3::new(attack_range / 4., 0., 0.);
+    let near = app.world.spawn(near).id();
+    spawn_species(&mut app, RED, raider_at);
+
+    run_ticks(&mut app, 1);
+
+    assert!(app.world.get::<Structure>(near).unwrap().health < config.wall_health);
+    assert_eq!(app.world.get::<Structure>(far).unwrap().health, config.wall_health);
+}
+
+
+#[test]
+fn tower_kills_are_credited_to_the_tower_race() {
+    let mut app = test_app(test_config("tower_kill"), CombatPlugin);
+    let config = EngineeringConfig::default();
+    let home = Homebase::new(Vec3::ZERO, RED, &HomebaseConfig::default());
+    let tower = Structure::new(StructureKind::Tower, &home, 1, &config);
+    let near = tower.position + Vec3::new(config.tower_range / 2., 0., 0.);
+    app.world.spawn(tower);
+    let enemy = spawn_species(&mut app, BLUE, near);
+    app.world.get_mut::<Health>(enemy).unwrap().val = config.tower_damage / 2.;
+    let mut reader = app.world.resource::<Events<Died>>().get_reader();
+
+    run_ticks(&mut app, 1);
+
+    let died: Vec<Died> = reader.read(app.world.resource::<Events<Died>>()).cloned().collect();
+    assert_eq!(died.len(), 1);
+    assert_eq!(died[0].entity, enemy);
+    assert_eq!(died[0].cause, Some(DeathCause::Structure(RED)));
+    assert_eq!(died[0].killer, None);
+}
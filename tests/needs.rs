@@ -0,0 +1,189 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::behavior::{MovementConfig, Steering};
+use evolution_sim_bevy::food_desire::*;
+use evolution_sim_bevy::food_source::FoodSource;
+use evolution_sim_bevy::health::*;
+use evolution_sim_bevy::species::*;
+use evolution_sim_bevy::water_desire::WaterDesire;
+use evolution_sim_bevy::weather::*;
+
+
+#[test]
+fn zero_water_takes_low_water_damage() {
+    let mut app = test_app(test_config("zero_water"), NeedsPlugin);
+    let thirsty = spawn_species(&mut app, RED, Vec3::ZERO);
+    let watered = spawn_species(&mut app, RED, Vec3::new(500., 0., 0.));
+    app.world.get_mut::<WaterDesire>(thirsty).unwrap().val = 0.;
+
+    let ticks = 60;
+    run_ticks(&mut app, ticks);
+
+    let config = HealthConfig::default();
+    // the first tick takes the water below zero, the damage starts on the next one
+    let expected = config.max_health - config.low_water_damage_rate * (ticks - 1) as f32 * TICK;
+    assert!((health(&app, thirsty) - expected).abs() < 1e-4, "health {} instead of {}", health(&app, thirsty), expected);
+    assert_eq!(health(&app, watered), config.max_health);
+}
+
+
+#[test]
+fn water_runs_out_at_the_thirst_rate() {
+    let mut app = test_app(test_config("thirst_rate"), NeedsPlugin);
+    let e = spawn_species(&mut app, RED, Vec3::ZERO);
+    let config = app.world.resource::<evolution_sim_bevy::config::SimConfig>().thirst.clone();
+
+    run_ticks(&mut app, 120);
+
+    let expected = config.max_water - config.thirst_rate_hz * 120. * TICK;
+    let water = app.world.get::<WaterDesire>(e).unwrap().val;
+    assert!((water - expected).abs() < 1e-4, "water {} instead of {}", water, expected);
+}
+
+
+#[test]
+fn deaths_report_their_cause() {
+    let mut app = test_app(test_config("death_cause"), (NeedsPlugin, CombatPlugin));
+    let thirsty = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<WaterDesire>(thirsty).unwrap().val = -1.;
+    app.world.get_mut::<Health>(thirsty).unwrap().val = 0.;
+    let mut reader = app.world.resource::<Events<Died>>().get_reader();
+
+    run_ticks(&mut app, 1);
+
+    let died: Vec<Died> = reader.read(app.world.resource::<Events<Died>>()).cloned().collect();
+    assert_eq!(died.len(), 1);
+    assert_eq!(died[0].entity, thirsty);
+    assert_eq!(died[0].race, RED);
+    assert_eq!(died[0].cause, Some(DeathCause::Dehydration));
+    assert_eq!(died[0].killer, None);
+    assert!(died[0].age > 0.);
+}
+
+
+#[test]
+fn elders_decline_and_die_of_old_age() {
+    let mut app = test_app(test_config("old_age"), (NeedsPlugin, CombatPlugin));
+    let config = AgingConfig::default();
+    let elder = spawn_species(&mut app, RED, Vec3::ZERO);
+    {
+        let mut sp = app.world.get_mut::<Species>(elder).unwrap();
+        sp.age = sp.lifespan * config.elder_fraction;
+    }
+
+    run_ticks(&mut app, 10);
+    assert!(health(&app, elder) < HealthConfig::default().max_health);
+
+    let mut reader = app.world.resource::<Events<Died>>().get_reader();
+    let lifespan = app.world.get::<Species>(elder).unwrap().lifespan;
+    app.world.get_mut::<Species>(elder).unwrap().age = lifespan;
+    run_ticks(&mut app, 1);
+
+    let died: Vec<Died> = reader.read(app.world.resource::<Events<Died>>()).cloned().collect();
+    assert_eq!(died.len(), 1);
+    assert_eq!(died[0].cause, Some(DeathCause::OldAge));
+}
+
+
+#[test]
+fn moving_costs_more_food_than_standing_still() {
+    let mut app = test_app(test_config("metabolism"), NeedsPlugin);
+    let still = spawn_species(&mut app, RED, Vec3::ZERO);
+    let moving = spawn_species(&mut app, RED, Vec3::new(500., 0., 0.));
+    let max_velocity = app.world.resource::<MovementConfig>().max_velocity;
+    app.world.get_mut::<Species>(moving).unwrap().velocity = Vec3::new(max_velocity, 0., 0.);
+
+    let ticks = 60;
+    run_ticks(&mut app, ticks);
+
+    // a grown species with an attack and defense of 1 and the default perception pays the base rate, its size and
+    // its senses. At full speed it pays the whole speed cost on top
+    let hunger = HungerConfig::default();
+    let metabolism = MetabolismConfig::default();
+    let rate = hunger.hunger_rate_hz + metabolism.size_cost + metabolism.perception_cost;
+    let food = |e| app.world.get::<FoodDesire>(e).unwrap().val;
+    let expected = hunger.max_hunger - rate * ticks as f32 * TICK;
+    assert!((food(still) - expected).abs() < 1e-4, "food {} instead of {}", food(still), expected);
+    let expected = expected - metabolism.speed_cost * ticks as f32 * TICK;
+    assert!((food(moving) - expected).abs() < 1e-4, "food {} instead of {}", food(moving), expected);
+}
+
+
+#[test]
+fn fed_species_heal_faster_at_home() {
+    let mut app = test_app(test_config("regeneration"), NeedsPlugin);
+    let config = HealthConfig::default();
+    // both have their homebase at the origin
+    let home = spawn_species(&mut app, RED, Vec3::ZERO);
+    let away = spawn_species(&mut app, RED, Vec3::new(config.home_radius * 2., 0., 0.));
+    for e in [home, away] {
+        let mut health = app.world.get_mut::<Health>(e).unwrap();
+        health.val = 1.;
+        health.injure(Injury::Limping, &config);
+    }
+
+    run_ticks(&mut app, 60);
+
+    let expected_away = 1. + config.regen_rate_hz * 60. * TICK;
+    let expected_home = 1. + config.regen_rate_hz * config.home_regen_factor * 60. * TICK;
+    assert!((health(&app, away) - expected_away).abs() < 1e-4, "health {} instead of {}", health(&app, away), expected_away);
+    assert!((health(&app, home) - expected_home).abs() < 1e-4, "health {} instead of {}", health(&app, home), expected_home);
+
+    let injury_time = |app: &App, e| app.world.get::<Health>(e).unwrap().injury_time;
+    assert!(injury_time(&app, home) < injury_time(&app, away));
+
+    // the injury heals, but starving species don't
+    app.world.get_mut::<FoodDesire>(away).unwrap().val = -1.;
+    let before = health(&app, away);
+    run_ticks(&mut app, (config.injury_sec / TICK) as u64);
+    assert_eq!(app.world.get::<Health>(away).unwrap().injury, None);
+    assert!(health(&app, away) < before);
+}
+
+
+
+#[test]
+fn hungry_species_only_go_for_food_they_can_see() {
+    let mut app = test_app(test_config("perception"), BehaviorPlugin);
+    let near = spawn_species(&mut app, RED, Vec3::ZERO);
+    let far = spawn_species(&mut app, RED, Vec3::new(0., 1000., 0.));
+    let perception = app.world.get::<Species>(near).unwrap().perception_radius;
+    let food = Vec3::new(perception / 2., 0., 0.);
+    app.world.spawn(FoodSource { value: 1., position: food, decay_rate_hz: 0. });
+    for e in [near, far] {
+        app.world.get_mut::<FoodDesire>(e).unwrap().val = -1.;
+    }
+
+    run_ticks(&mut app, 1);
+
+    let target = |e| app.world.get::<Steering>(e).unwrap().food.map(|(target, _)| target);
+    assert_eq!(target(near), Some(food));
+    assert_eq!(target(far), None);
+}
+
+
+#[test]
+fn storms_hurt_the_species_away_from_home() {
+    let mut app = test_app(test_config("storm"), (NeedsPlugin, CombatPlugin));
+    app.world.resource_mut::<Weather>().current = WeatherKind::Storm;
+    let home_radius = HealthConfig::default().home_radius;
+    let sheltered = spawn_species(&mut app, RED, Vec3::ZERO);
+    let outside = spawn_species(&mut app, RED, Vec3::new(home_radius * 2., 0., 0.));
+    for e in [sheltered, outside] {
+        app.world.get_mut::<Species>(e).unwrap().homebase = Some(Vec3::ZERO);
+    }
+    app.world.get_mut::<Health>(outside).unwrap().val = 0.001;
+    let full = health(&app, sheltered);
+    let mut reader = app.world.resource::<Events<Died>>().get_reader();
+
+    run_ticks(&mut app, 1);
+
+    assert_eq!(health(&app, sheltered), full);
+    let died: Vec<Died> = reader.read(app.world.resource::<Events<Died>>()).cloned().collect();
+    assert_eq!(died.len(), 1);
+    assert_eq!(died[0].entity, outside);
+    assert_eq!(died[0].cause, Some(DeathCause::Environment));
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::clock::SimClock;
+use evolution_sim_bevy::replay::Replay;
+
+
+#[test]
+fn replay_checkpoints_are_thinned_out() {
+    let mut config = test_config("replay_checkpoints");
+    config.replay.checkpoint_interval_sec = TICK;
+    config.replay.max_checkpoints = 4;
+    let mut app = test_app(config, NeedsPlugin);
+    run_ticks(&mut app, 1);
+    let first = *app.world.resource::<Replay>().checkpoints.keys().next().unwrap();
+
+    run_ticks(&mut app, 20);
+
+    let ticks: Vec<u64> = app.world.resource::<Replay>().checkpoints.keys().copied().collect();
+    assert_eq!(ticks.len(), 4);
+    assert_eq!(ticks[0], first, "the start is kept");
+    assert_eq!(*ticks.last().unwrap(), app.world.resource::<SimClock>().frame - 1, "the newest is kept");
+}
+
+
+#[test]
+fn nothing_is_recorded_when_replays_are_off() {
+    let mut config = test_config("replay_off");
+    config.replay.enabled = false;
+    config.replay.checkpoint_interval_sec = TICK;
+    let mut app = test_app(config, NeedsPlugin);
+
+    run_ticks(&mut app, 5);
+
+    let replay = app.world.resource::<Replay>();
+    assert!(replay.checkpoints.is_empty());
+    assert!(replay.inputs.is_empty());
+}
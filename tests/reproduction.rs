@@ -0,0 +1,83 @@
+mod common;
+
+use bevy::prelude::*;
+use common::*;
+use evolution_sim_bevy::*;
+use evolution_sim_bevy::fight::Fight;
+use evolution_sim_bevy::reproduce::ReproductionConfig;
+use evolution_sim_bevy::species::Species;
+
+
+#[test]
+fn no_reproduction_within_the_grace_period() {
+    let mut app = test_app(test_config("grace_period"), ReproductionPlugin);
+    spawn_species(&mut app, RED, Vec3::ZERO);
+
+    // a species with full stats always scores above the threshold, only the grace period holds it back
+    let grace_ticks = (ReproductionConfig::default().grace_period_sec / TICK).floor() as u64;
+    run_ticks(&mut app, grace_ticks - 1);
+    assert_eq!(count_species(&mut app), 1);
+
+    run_ticks(&mut app, 3);
+    assert_eq!(count_species(&mut app), 2);
+
+    // the parent starts a new grace period and so does the child
+    run_ticks(&mut app, grace_ticks / 2);
+    assert_eq!(count_species(&mut app), 2);
+}
+
+
+#[test]
+fn grace_period_comes_from_the_config() {
+    let mut config = test_config("short_grace_period");
+    config.reproduction.grace_period_sec = 1.;
+    let mut app = test_app(config, ReproductionPlugin);
+    spawn_species(&mut app, RED, Vec3::ZERO);
+
+    run_ticks(&mut app, 55);
+    assert_eq!(count_species(&mut app), 1);
+
+    run_ticks(&mut app, 10);
+    assert_eq!(count_species(&mut app), 2);
+}
+
+
+#[test]
+fn children_inherit_attack_and_defense_with_a_mutation() {
+    let mut config = test_config("mutation");
+    config.reproduction.grace_period_sec = 1.;
+    config.reproduction.mutation = 0.1;
+    let mut app = test_app(config, ReproductionPlugin);
+    let parent = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.entity_mut(parent).insert(Fight::new(2., 3.));
+
+    run_ticks(&mut app, 65);
+
+    let fights: Vec<(Entity, Fight)> = app.world.query::<(Entity, &Fight)>().iter(&app.world)
+        .map(|(e, f)| (e, f.clone())).collect();
+    let (_, child) = fights.iter().find(|(e, _)| *e != parent).expect("no child was born");
+    assert!((1.8..=2.2).contains(&child.attack_val), "attack {}", child.attack_val);
+    assert!((2.7..=3.3).contains(&child.defense_val), "defense {}", child.defense_val);
+    assert!(child.attack_val != 2. || child.defense_val != 3., "nothing mutated");
+}
+
+
+#[test]
+fn children_inherit_the_lifespan_with_a_mutation() {
+    let mut config = test_config("lifespan");
+    config.reproduction.grace_period_sec = 1.;
+    config.reproduction.mutation = 0.1;
+    let mut app = test_app(config, ReproductionPlugin);
+    let parent = spawn_species(&mut app, RED, Vec3::ZERO);
+    app.world.get_mut::<Species>(parent).unwrap().lifespan = 200.;
+
+    run_ticks(&mut app, 65);
+
+    let lifespans: Vec<f32> = app.world.query::<(Entity, &Species)>().iter(&app.world)
+        .filter(|(e, _)| *e != parent)
+        .map(|(_, sp)| sp.lifespan).collect();
+    assert!(!lifespans.is_empty(), "no child was born");
+    for lifespan in lifespans {
+        assert!((180.0..=220.).contains(&lifespan), "lifespan {}", lifespan);
+    }
+}